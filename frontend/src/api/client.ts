@@ -1,5 +1,5 @@
 import axios from 'axios';
-import { CreateLinkRequest, Link, AuthResponse, ClickStats, SourceStats } from '../types/api';
+import { CreateLinkRequest, Link, LinkPage, LinkListParams, AuthResponse, ClickStats, SourceStats } from '../types/api';
 
 // Create axios instance with default config
 const api = axios.create({
@@ -53,8 +53,8 @@ export const createShortLink = async (data: CreateLinkRequest) => {
 	return response.data;
 };
 
-export const getAllLinks = async () => {
-	const response = await api.get<Link[]>('/links');
+export const getAllLinks = async (params: LinkListParams = {}) => {
+	const response = await api.get<LinkPage>('/links', { params });
 	return response.data;
 };
 
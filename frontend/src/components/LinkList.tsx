@@ -11,6 +11,7 @@ import {
 	TableRow,
 } from "@/components/ui/table"
 import { Button } from "@/components/ui/button"
+import { Input } from "@/components/ui/input"
 import { useToast } from "@/hooks/use-toast"
 import { Copy, Trash2, BarChart2, Pencil } from "lucide-react"
 import {
@@ -32,6 +33,8 @@ interface LinkListProps {
 export function LinkList({ refresh = 0 }: LinkListProps) {
 	const [links, setLinks] = useState<Link[]>([])
 	const [loading, setLoading] = useState(true)
+	const [search, setSearch] = useState('')
+	const [nextCursor, setNextCursor] = useState<string | null>(null)
 	const [deleteModal, setDeleteModal] = useState<{ isOpen: boolean; linkId: number | null }>({
 		isOpen: false,
 		linkId: null,
@@ -46,11 +49,12 @@ export function LinkList({ refresh = 0 }: LinkListProps) {
 	});
 	const { toast } = useToast()
 
-	const fetchLinks = useCallback(async () => {
+	const fetchLinks = useCallback(async (cursor?: string) => {
 		try {
 			setLoading(true)
-			const data = await getAllLinks()
-			setLinks(data)
+			const page = await getAllLinks({ q: search || undefined, cursor })
+			setLinks(prev => cursor ? [...prev, ...page.links] : page.links)
+			setNextCursor(page.next_cursor)
 		} catch (err: unknown) {
 			const errorMessage = err instanceof Error ? err.message : 'Unknown error occurred';
 			toast({
@@ -61,10 +65,12 @@ export function LinkList({ refresh = 0 }: LinkListProps) {
 		} finally {
 			setLoading(false)
 		}
-	}, [toast, setLinks, setLoading])
+	}, [toast, search, setLinks, setLoading])
 
 	useEffect(() => {
-		fetchLinks()
+		// Debounce so typing in the search box doesn't fire a request per keystroke
+		const timeout = setTimeout(() => fetchLinks(), 300)
+		return () => clearTimeout(timeout)
 	}, [fetchLinks, refresh]) // Re-fetch when refresh counter changes
 
 	const handleDelete = async () => {
@@ -102,7 +108,7 @@ export function LinkList({ refresh = 0 }: LinkListProps) {
 		})
 	}
 
-	if (loading && !links.length) {
+	if (loading && !links.length && !search) {
 		return <div className="text-center py-4">Loading...</div>
 	}
 
@@ -132,7 +138,12 @@ export function LinkList({ refresh = 0 }: LinkListProps) {
 					<CardTitle>Your Links</CardTitle>
 					<CardDescription>Manage and track your shortened links</CardDescription>
 				</CardHeader>
-				<CardContent>
+				<CardContent className="space-y-4">
+					<Input
+						placeholder="Search by code or URL"
+						value={search}
+						onChange={(e) => setSearch(e.target.value)}
+					/>
 					<div className="rounded-md border">
 
 						<Table>
@@ -201,6 +212,13 @@ export function LinkList({ refresh = 0 }: LinkListProps) {
 							</TableBody>
 						</Table>
 					</div>
+					{nextCursor && (
+						<div className="flex justify-center">
+							<Button variant="outline" disabled={loading} onClick={() => fetchLinks(nextCursor)}>
+								{loading ? 'Loading...' : 'Load more'}
+							</Button>
+						</div>
+					)}
 				</CardContent>
 			</Card>
 			<StatisticsModal
@@ -213,7 +231,7 @@ export function LinkList({ refresh = 0 }: LinkListProps) {
 					isOpen={editModal.isOpen}
 					onClose={() => setEditModal({ isOpen: false, link: null })}
 					link={editModal.link}
-					onSuccess={fetchLinks}
+					onSuccess={() => fetchLinks()}
 				/>
 			)}
 		</>
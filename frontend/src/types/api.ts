@@ -12,6 +12,23 @@ export interface Link {
 	clicks: number;
 }
 
+export interface LinkPage {
+	links: Link[];
+	next_cursor: string | null;
+}
+
+export interface LinkListParams {
+	limit?: number;
+	cursor?: string;
+	q?: string;
+	created_after?: string;
+	created_before?: string;
+	min_clicks?: number;
+	max_clicks?: number;
+	sort?: 'created_at' | 'clicks' | 'short_code';
+	order?: 'asc' | 'desc';
+}
+
 export interface User {
 	id: number;
 	email: string;
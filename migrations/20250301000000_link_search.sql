@@ -0,0 +1,8 @@
+-- Full-text search over links. The vector is a stored generated column, so
+-- every INSERT/UPDATE from the handlers keeps it current without extra queries.
+ALTER TABLE links ADD COLUMN search_vector tsvector GENERATED ALWAYS AS (
+    setweight(to_tsvector('simple', regexp_replace(short_code, '[^[:alnum:]]+', ' ', 'g')), 'A') ||
+    setweight(to_tsvector('simple', regexp_replace(original_url, '[^[:alnum:]]+', ' ', 'g')), 'B')
+) STORED;
+
+CREATE INDEX idx_links_search ON links USING GIN (search_vector);
@@ -0,0 +1,18 @@
+-- Optional descriptive fields. Notes are private to the link's owner.
+ALTER TABLE links ADD COLUMN title TEXT;
+ALTER TABLE links ADD COLUMN description TEXT;
+ALTER TABLE links ADD COLUMN notes TEXT;
+
+-- Rebuild the search vector so it covers the new fields
+DROP INDEX idx_links_search;
+ALTER TABLE links DROP COLUMN search_vector;
+
+ALTER TABLE links ADD COLUMN search_vector tsvector GENERATED ALWAYS AS (
+    setweight(to_tsvector('simple', regexp_replace(short_code, '[^[:alnum:]]+', ' ', 'g')), 'A') ||
+    setweight(to_tsvector('simple', coalesce(title, '')), 'A') ||
+    setweight(to_tsvector('simple', regexp_replace(original_url, '[^[:alnum:]]+', ' ', 'g')), 'B') ||
+    setweight(to_tsvector('simple', coalesce(description, '')), 'C') ||
+    setweight(to_tsvector('simple', coalesce(notes, '')), 'C')
+) STORED;
+
+CREATE INDEX idx_links_search ON links USING GIN (search_vector);
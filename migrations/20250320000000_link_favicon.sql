@@ -0,0 +1,2 @@
+-- Favicon of the destination page, filled in by the metadata fetcher
+ALTER TABLE links ADD COLUMN favicon_url TEXT;
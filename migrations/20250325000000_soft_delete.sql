@@ -0,0 +1,4 @@
+-- Deleted links stay in the trash, with their clicks, until they are purged
+ALTER TABLE links ADD COLUMN deleted_at TIMESTAMPTZ;
+
+CREATE INDEX idx_links_deleted_at ON links(deleted_at) WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,13 @@
+-- Admins can manage instance-wide state such as retired short codes. The
+-- first user, who set the instance up, is the admin.
+ALTER TABLE users ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE users SET is_admin = TRUE WHERE id = (SELECT MIN(id) FROM users);
+
+-- Short codes released by purged or renamed links. Other users can't claim
+-- them until expires_at; a NULL expires_at blocks reuse permanently.
+CREATE TABLE code_tombstones (
+    short_code VARCHAR(32) PRIMARY KEY,
+    user_id INTEGER REFERENCES users(id),
+    released_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ
+);
@@ -0,0 +1,23 @@
+-- Extra short codes that resolve to a link besides links.short_code. A
+-- renamed link keeps its old code here so published URLs keep working.
+CREATE TABLE link_codes (
+    short_code VARCHAR(32) PRIMARY KEY,
+    link_id INTEGER NOT NULL REFERENCES links(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_link_codes_link_id ON link_codes(link_id);
+
+-- Changes made to a link's destination or short code
+CREATE TABLE link_revisions (
+    id SERIAL PRIMARY KEY,
+    link_id INTEGER NOT NULL REFERENCES links(id) ON DELETE CASCADE,
+    user_id INTEGER REFERENCES users(id),
+    old_url TEXT NOT NULL,
+    new_url TEXT NOT NULL,
+    old_code VARCHAR(32) NOT NULL,
+    new_code VARCHAR(32) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_link_revisions_link_id ON link_revisions(link_id);
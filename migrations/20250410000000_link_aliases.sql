@@ -0,0 +1,4 @@
+-- The code a click came in through, so clicks can be counted per alias.
+-- Existing clicks are attributed to the link's current short code.
+ALTER TABLE clicks ADD COLUMN short_code VARCHAR(32);
+UPDATE clicks SET short_code = links.short_code FROM links WHERE links.id = clicks.link_id;
@@ -0,0 +1,3 @@
+-- Bumped on every edit and exposed as the link's ETag, so that concurrent
+-- edits can be detected with If-Match
+ALTER TABLE links ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,7 @@
+-- Source of the numbers behind SHORT_CODE_STRATEGY=counter
+CREATE TABLE short_code_counter (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    value BIGINT NOT NULL
+);
+
+INSERT INTO short_code_counter (id, value) VALUES (1, 0);
@@ -0,0 +1,3 @@
+-- Looking up a user's existing link to a destination when deduplicating.
+-- A hash index, since destinations can be longer than a btree entry allows.
+CREATE INDEX idx_links_original_url ON links USING hash (original_url);
@@ -0,0 +1,15 @@
+-- Allow and deny rules for destination hosts. Rules without a user_id apply
+-- to every user.
+CREATE TABLE domain_rules (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER REFERENCES users(id),
+    pattern VARCHAR(255) NOT NULL,
+    action VARCHAR(5) NOT NULL CHECK (action IN ('allow', 'deny')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_domain_rules_user_id ON domain_rules(user_id);
+
+-- Set on links whose destination breaks a rule added after they were created
+ALTER TABLE links ADD COLUMN flagged_at TIMESTAMPTZ;
+ALTER TABLE links ADD COLUMN flag_reason TEXT;
@@ -0,0 +1,22 @@
+-- Reports of abusive short links sent through /api/report, waiting for an
+-- admin to review them
+CREATE TABLE abuse_reports (
+    id SERIAL PRIMARY KEY,
+    link_id INTEGER NOT NULL REFERENCES links(id) ON DELETE CASCADE,
+    short_code VARCHAR(32) NOT NULL,
+    reason TEXT NOT NULL,
+    reporter_ip TEXT,
+    status VARCHAR(16) NOT NULL DEFAULT 'open'
+        CHECK (status IN ('open', 'dismissed', 'actioned')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ,
+    resolved_by INTEGER REFERENCES users(id)
+);
+
+CREATE INDEX idx_abuse_reports_status ON abuse_reports(status);
+CREATE INDEX idx_abuse_reports_link_id ON abuse_reports(link_id);
+
+-- Banned users can't sign in or use their tokens, and their links are
+-- disabled
+ALTER TABLE users ADD COLUMN banned_at TIMESTAMPTZ;
+ALTER TABLE users ADD COLUMN ban_reason TEXT;
@@ -0,0 +1,7 @@
+-- Disabled links stay in place with their clicks but no longer redirect.
+-- Owners can only re-enable links they disabled themselves; links disabled
+-- by an admin or a blocklist have to be re-enabled by an admin.
+ALTER TABLE links ADD COLUMN enabled BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE links ADD COLUMN disabled_reason TEXT;
+ALTER TABLE links ADD COLUMN disabled_at TIMESTAMPTZ;
+ALTER TABLE links ADD COLUMN disabled_by INTEGER REFERENCES users(id);
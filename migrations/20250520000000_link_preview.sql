@@ -0,0 +1,5 @@
+-- Links with preview set show an interstitial page with their destination
+-- instead of redirecting straight away. The page names the link's owner by
+-- their display name.
+ALTER TABLE links ADD COLUMN preview BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE users ADD COLUMN display_name VARCHAR(100);
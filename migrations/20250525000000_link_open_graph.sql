@@ -0,0 +1,5 @@
+-- Open Graph tags served to link unfurling crawlers such as Slackbot instead
+-- of the redirect, overriding what the destination page says about itself.
+ALTER TABLE links ADD COLUMN og_title TEXT;
+ALTER TABLE links ADD COLUMN og_description TEXT;
+ALTER TABLE links ADD COLUMN og_image TEXT;
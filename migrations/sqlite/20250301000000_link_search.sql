@@ -0,0 +1,29 @@
+-- Full-text search over links, backed by an external-content FTS5 table
+CREATE VIRTUAL TABLE links_fts USING fts5(
+    short_code,
+    original_url,
+    content='links',
+    content_rowid='id'
+);
+
+-- Index the links that already exist
+INSERT INTO links_fts(rowid, short_code, original_url)
+SELECT id, short_code, original_url FROM links;
+
+-- Keep the index in sync with writes to links
+CREATE TRIGGER links_fts_insert AFTER INSERT ON links BEGIN
+    INSERT INTO links_fts(rowid, short_code, original_url)
+    VALUES (new.id, new.short_code, new.original_url);
+END;
+
+CREATE TRIGGER links_fts_delete AFTER DELETE ON links BEGIN
+    INSERT INTO links_fts(links_fts, rowid, short_code, original_url)
+    VALUES ('delete', old.id, old.short_code, old.original_url);
+END;
+
+CREATE TRIGGER links_fts_update AFTER UPDATE OF short_code, original_url ON links BEGIN
+    INSERT INTO links_fts(links_fts, rowid, short_code, original_url)
+    VALUES ('delete', old.id, old.short_code, old.original_url);
+    INSERT INTO links_fts(rowid, short_code, original_url)
+    VALUES (new.id, new.short_code, new.original_url);
+END;
@@ -0,0 +1,41 @@
+-- Optional descriptive fields. Notes are private to the link's owner.
+ALTER TABLE links ADD COLUMN title TEXT;
+ALTER TABLE links ADD COLUMN description TEXT;
+ALTER TABLE links ADD COLUMN notes TEXT;
+
+-- Rebuild the search index so it covers the new fields
+DROP TRIGGER links_fts_insert;
+DROP TRIGGER links_fts_delete;
+DROP TRIGGER links_fts_update;
+DROP TABLE links_fts;
+
+CREATE VIRTUAL TABLE links_fts USING fts5(
+    short_code,
+    original_url,
+    title,
+    description,
+    notes,
+    content='links',
+    content_rowid='id'
+);
+
+INSERT INTO links_fts(rowid, short_code, original_url, title, description, notes)
+SELECT id, short_code, original_url, title, description, notes FROM links;
+
+CREATE TRIGGER links_fts_insert AFTER INSERT ON links BEGIN
+    INSERT INTO links_fts(rowid, short_code, original_url, title, description, notes)
+    VALUES (new.id, new.short_code, new.original_url, new.title, new.description, new.notes);
+END;
+
+CREATE TRIGGER links_fts_delete AFTER DELETE ON links BEGIN
+    INSERT INTO links_fts(links_fts, rowid, short_code, original_url, title, description, notes)
+    VALUES ('delete', old.id, old.short_code, old.original_url, old.title, old.description, old.notes);
+END;
+
+CREATE TRIGGER links_fts_update
+AFTER UPDATE OF short_code, original_url, title, description, notes ON links BEGIN
+    INSERT INTO links_fts(links_fts, rowid, short_code, original_url, title, description, notes)
+    VALUES ('delete', old.id, old.short_code, old.original_url, old.title, old.description, old.notes);
+    INSERT INTO links_fts(rowid, short_code, original_url, title, description, notes)
+    VALUES (new.id, new.short_code, new.original_url, new.title, new.description, new.notes);
+END;
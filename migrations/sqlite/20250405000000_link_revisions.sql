@@ -0,0 +1,26 @@
+-- Extra short codes that resolve to a link besides links.short_code. A
+-- renamed link keeps its old code here so published URLs keep working.
+CREATE TABLE link_codes (
+    short_code VARCHAR(32) PRIMARY KEY,
+    link_id INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (link_id) REFERENCES links(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_link_codes_link_id ON link_codes(link_id);
+
+-- Changes made to a link's destination or short code
+CREATE TABLE link_revisions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    link_id INTEGER NOT NULL,
+    user_id INTEGER,
+    old_url TEXT NOT NULL,
+    new_url TEXT NOT NULL,
+    old_code VARCHAR(32) NOT NULL,
+    new_code VARCHAR(32) NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (link_id) REFERENCES links(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX idx_link_revisions_link_id ON link_revisions(link_id);
@@ -0,0 +1,11 @@
+-- Every code a link answers to lives in link_codes, its own short_code
+-- included, and redirects resolve through this table alone. links.short_code
+-- still names the link's primary code.
+INSERT INTO link_codes (short_code, link_id, created_at)
+SELECT short_code, id, created_at FROM links
+WHERE true
+ON CONFLICT (short_code) DO NOTHING;
+
+-- Clicks recorded without a code came in through the link's short code
+UPDATE clicks SET short_code = (SELECT short_code FROM links WHERE links.id = clicks.link_id)
+WHERE short_code IS NULL;
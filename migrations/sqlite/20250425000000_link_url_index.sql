@@ -0,0 +1,2 @@
+-- Looking up a user's existing link to a destination when deduplicating
+CREATE INDEX idx_links_original_url ON links(original_url);
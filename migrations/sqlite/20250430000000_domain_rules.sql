@@ -0,0 +1,16 @@
+-- Allow and deny rules for destination hosts. Rules without a user_id apply
+-- to every user.
+CREATE TABLE domain_rules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER,
+    pattern VARCHAR(255) NOT NULL,
+    action VARCHAR(5) NOT NULL CHECK (action IN ('allow', 'deny')),
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX idx_domain_rules_user_id ON domain_rules(user_id);
+
+-- Set on links whose destination breaks a rule added after they were created
+ALTER TABLE links ADD COLUMN flagged_at TEXT;
+ALTER TABLE links ADD COLUMN flag_reason TEXT;
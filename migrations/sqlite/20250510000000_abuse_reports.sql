@@ -0,0 +1,24 @@
+-- Reports of abusive short links sent through /api/report, waiting for an
+-- admin to review them
+CREATE TABLE abuse_reports (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    link_id INTEGER NOT NULL,
+    short_code VARCHAR(32) NOT NULL,
+    reason TEXT NOT NULL,
+    reporter_ip TEXT,
+    status VARCHAR(16) NOT NULL DEFAULT 'open'
+        CHECK (status IN ('open', 'dismissed', 'actioned')),
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    resolved_at TEXT,
+    resolved_by INTEGER,
+    FOREIGN KEY (link_id) REFERENCES links(id) ON DELETE CASCADE,
+    FOREIGN KEY (resolved_by) REFERENCES users(id)
+);
+
+CREATE INDEX idx_abuse_reports_status ON abuse_reports(status);
+CREATE INDEX idx_abuse_reports_link_id ON abuse_reports(link_id);
+
+-- Banned users can't sign in or use their tokens, and their links are
+-- disabled
+ALTER TABLE users ADD COLUMN banned_at TEXT;
+ALTER TABLE users ADD COLUMN ban_reason TEXT;
@@ -0,0 +1,2 @@
+-- Links whose owners opted in to the public click badge and sparkline
+ALTER TABLE links ADD COLUMN embeddable BOOLEAN NOT NULL DEFAULT FALSE;
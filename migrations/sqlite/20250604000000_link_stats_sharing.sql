@@ -0,0 +1,5 @@
+-- Public stats pages. public_stats publishes a link's stats at
+-- /{short_code}/stats, while share links carry a token signed over the
+-- nonce, so replacing or clearing the nonce revokes them.
+ALTER TABLE links ADD COLUMN public_stats BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE links ADD COLUMN stats_share_nonce TEXT;
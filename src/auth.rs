@@ -1,7 +1,50 @@
-use crate::{error::AppError, models::Claims};
-use actix_web::{dev::Payload, FromRequest, HttpRequest};
+use crate::{
+    error::AppError,
+    models::{Claims, DatabasePool},
+    AppState,
+};
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
 use jsonwebtoken::{decode, DecodingKey, Validation};
+use std::collections::HashSet;
 use std::future::{ready, Ready};
+use std::sync::{Arc, RwLock};
+
+/// Users an admin has banned. Kept in memory so that checking a token
+/// doesn't need a database query.
+#[derive(Clone, Default)]
+pub struct BannedUsers(Arc<RwLock<HashSet<i32>>>);
+
+impl BannedUsers {
+    pub async fn load(db: &DatabasePool) -> Result<Self, sqlx::Error> {
+        let ids = match db {
+            DatabasePool::Postgres(pool) => {
+                sqlx::query_as::<_, (i32,)>("SELECT id FROM users WHERE banned_at IS NOT NULL")
+                    .fetch_all(pool)
+                    .await?
+            }
+            DatabasePool::Sqlite(pool) => {
+                sqlx::query_as::<_, (i32,)>("SELECT id FROM users WHERE banned_at IS NOT NULL")
+                    .fetch_all(pool)
+                    .await?
+            }
+        };
+        Ok(Self(Arc::new(RwLock::new(
+            ids.into_iter().map(|(id,)| id).collect(),
+        ))))
+    }
+
+    pub fn contains(&self, user_id: i32) -> bool {
+        self.0.read().unwrap().contains(&user_id)
+    }
+
+    pub fn insert(&self, user_id: i32) {
+        self.0.write().unwrap().insert(user_id);
+    }
+
+    pub fn remove(&self, user_id: i32) {
+        self.0.write().unwrap().remove(&user_id);
+    }
+}
 
 pub struct AuthenticatedUser {
     pub user_id: i32,
@@ -18,8 +61,7 @@ impl FromRequest for AuthenticatedUser {
             .and_then(|h| h.to_str().ok());
 
         if let Some(auth_header) = auth_header {
-            if auth_header.starts_with("Bearer ") {
-                let token = &auth_header[7..];
+            if let Some(token) = auth_header.strip_prefix("Bearer ") {
                 let secret =
                     std::env::var("JWT_SECRET").unwrap_or_else(|_| "default_secret".to_string());
                 match decode::<Claims>(
@@ -28,6 +70,12 @@ impl FromRequest for AuthenticatedUser {
                     &Validation::default(),
                 ) {
                     Ok(token_data) => {
+                        let banned = req
+                            .app_data::<web::Data<AppState>>()
+                            .is_some_and(|state| state.banned.contains(token_data.claims.sub));
+                        if banned {
+                            return ready(Err(AppError::Banned));
+                        }
                         return ready(Ok(AuthenticatedUser {
                             user_id: token_data.claims.sub,
                         }));
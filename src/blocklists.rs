@@ -0,0 +1,373 @@
+//! Phishing and malware blocklists loaded from local files.
+//!
+//! `BLOCKLISTS` lists the files as comma-separated `format:path` entries,
+//! where the format is one of:
+//! - `hosts`: one host name per line, or a hosts file (`0.0.0.0 host`). A
+//!   listed host also blocks its subdomains.
+//! - `urlhaus`: a URLhaus CSV export, blocking the exact URLs it lists
+//! - `hash_prefixes`: hex SHA-256 hash prefixes of URL expressions, as in
+//!   Safe Browsing's update API. Only a full 32-byte hash confirms a hit,
+//!   since there is no server to confirm a shorter prefix against.
+//!
+//! Destinations are checked against the lists when links are created or
+//! edited. A background job re-reads the files every
+//! `BLOCKLIST_RECHECK_MINUTES` minutes and flags existing links that have
+//! ended up on one of them, disabling those with a confirmed hit.
+
+use crate::destinations::DestinationPolicy;
+use crate::models::DatabasePool;
+use anyhow::{anyhow, bail, Context};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashSet};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+const DEFAULT_RECHECK_MINUTES: u64 = 60;
+/// Entries of hosts files that aren't meant to be blocked
+const HOSTS_FILE_NAMES: &[&str] = &[
+    "localhost",
+    "localhost.localdomain",
+    "local",
+    "broadcasthost",
+    "ip6-localhost",
+    "ip6-loopback",
+    "0.0.0.0",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Hosts,
+    Urlhaus,
+    HashPrefixes,
+}
+
+#[derive(Clone, Debug)]
+struct Source {
+    format: Format,
+    path: PathBuf,
+}
+
+impl Source {
+    fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.path.display().to_string())
+    }
+}
+
+#[derive(Default)]
+struct Blocklist {
+    name: String,
+    hosts: HashSet<String>,
+    urls: HashSet<String>,
+    prefixes: HashSet<Vec<u8>>,
+    prefix_lengths: BTreeSet<usize>,
+}
+
+impl Blocklist {
+    fn load(source: &Source) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(&source.path)
+            .with_context(|| format!("Failed to read blocklist {}", source.path.display()))?;
+        let mut list = Blocklist {
+            name: source.name(),
+            ..Default::default()
+        };
+
+        match source.format {
+            Format::Hosts => {
+                for line in contents.lines() {
+                    let line = line.split('#').next().unwrap_or_default();
+                    let tokens: Vec<&str> = line.split_whitespace().collect();
+                    let host = match tokens.as_slice() {
+                        [] => continue,
+                        [ip, host, ..] if ip.parse::<IpAddr>().is_ok() => *host,
+                        [host, ..] => *host,
+                    };
+                    let host = host.trim_end_matches('.').to_lowercase();
+                    if !HOSTS_FILE_NAMES.contains(&host.as_str()) {
+                        list.hosts.insert(host);
+                    }
+                }
+            }
+            Format::Urlhaus => {
+                let mut reader = csv::ReaderBuilder::new()
+                    .has_headers(false)
+                    .comment(Some(b'#'))
+                    .flexible(true)
+                    .from_reader(contents.as_bytes());
+                for record in reader.records() {
+                    let record = record.with_context(|| {
+                        format!("Invalid URLhaus CSV in {}", source.path.display())
+                    })?;
+                    // id, dateadded, url, url_status, ...
+                    if let Some(url) = record.get(2).and_then(|url| Url::parse(url).ok()) {
+                        list.urls.insert(url.into());
+                    }
+                }
+            }
+            Format::HashPrefixes => {
+                for (number, line) in contents.lines().enumerate() {
+                    let line = line.split('#').next().unwrap_or_default().trim();
+                    if line.is_empty() {
+                        continue;
+                    }
+                    let prefix = decode_hex(line)
+                        .filter(|prefix| (4..=32).contains(&prefix.len()))
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "Invalid hash prefix on line {} of {}, expected 8 to 64 hex digits",
+                                number + 1,
+                                source.path.display()
+                            )
+                        })?;
+                    list.prefix_lengths.insert(prefix.len());
+                    list.prefixes.insert(prefix);
+                }
+            }
+        }
+        Ok(list)
+    }
+
+    fn entries(&self) -> usize {
+        self.hosts.len() + self.urls.len() + self.prefixes.len()
+    }
+
+    /// Whether the list has the URL, and if so whether the hit is confirmed.
+    fn lookup(&self, url: &Url, host: &str) -> Option<bool> {
+        if !self.hosts.is_empty() && parent_domains(host).any(|h| self.hosts.contains(h)) {
+            return Some(true);
+        }
+        if self.urls.contains(url.as_str()) {
+            return Some(true);
+        }
+        let mut found = None;
+        if !self.prefixes.is_empty() {
+            for expression in url_expressions(url, host) {
+                let hash = Sha256::digest(expression.as_bytes());
+                for &len in &self.prefix_lengths {
+                    if self.prefixes.contains(&hash[..len]) {
+                        if len == hash.len() {
+                            return Some(true);
+                        }
+                        found = Some(false);
+                    }
+                }
+            }
+        }
+        found
+    }
+}
+
+/// A destination found on one of the blocklists.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlocklistHit {
+    /// Why the URL is blocked, naming the list
+    pub reason: String,
+    /// Whether the list names the URL or its host, or has its full hash.
+    /// A hash prefix alone may belong to a different URL.
+    pub confirmed: bool,
+}
+
+/// The configured blocklists. Cloning is cheap, all clones share the lists
+/// loaded last.
+#[derive(Clone, Default)]
+pub struct Blocklists {
+    sources: Arc<Vec<Source>>,
+    lists: Arc<RwLock<Vec<Blocklist>>>,
+}
+
+impl Blocklists {
+    /// Reads `BLOCKLISTS` and loads every file it names.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut sources = Vec::new();
+        for entry in std::env::var("BLOCKLISTS").unwrap_or_default().split(',') {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let Some((format, path)) = entry.split_once(':') else {
+                bail!("Invalid BLOCKLISTS entry {:?}, expected format:path", entry);
+            };
+            let format = match format {
+                "hosts" => Format::Hosts,
+                "urlhaus" => Format::Urlhaus,
+                "hash_prefixes" => Format::HashPrefixes,
+                other => bail!(
+                    "Unknown blocklist format {:?}, expected hosts, urlhaus or hash_prefixes",
+                    other
+                ),
+            };
+            sources.push(Source {
+                format,
+                path: PathBuf::from(path),
+            });
+        }
+
+        let lists = sources
+            .iter()
+            .map(Blocklist::load)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        for list in &lists {
+            tracing::info!(
+                "Loaded blocklist {} with {} entries",
+                list.name,
+                list.entries()
+            );
+        }
+
+        Ok(Self {
+            sources: Arc::new(sources),
+            lists: Arc::new(RwLock::new(lists)),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    /// Re-reads the files. A file that can't be read keeps its previous
+    /// entries.
+    pub fn reload(&self) {
+        let loaded: Vec<_> = self.sources.iter().map(Blocklist::load).collect();
+        let mut lists = self.lists.write().unwrap();
+        for (index, list) in loaded.into_iter().enumerate() {
+            match list {
+                Ok(list) => lists[index] = list,
+                Err(err) => tracing::error!("{:#}", err),
+            }
+        }
+    }
+
+    /// Whether the URL is on one of the lists, preferring a confirmed hit
+    /// to a hash prefix match.
+    pub fn hit(&self, url: &Url) -> Option<BlocklistHit> {
+        let host = url.host_str()?.trim_end_matches('.');
+        let lists = self.lists.read().unwrap();
+        let mut hit = None;
+        for list in lists.iter() {
+            match list.lookup(url, host) {
+                Some(true) => {
+                    return Some(BlocklistHit {
+                        reason: format!("URL is on the blocklist {}", list.name),
+                        confirmed: true,
+                    })
+                }
+                Some(false) if hit.is_none() => {
+                    hit = Some(BlocklistHit {
+                        reason: format!("URL matches a hash prefix on the blocklist {}", list.name),
+                        confirmed: false,
+                    })
+                }
+                _ => {}
+            }
+        }
+        hit
+    }
+}
+
+/// Starts the job that reloads the blocklists and flags links whose
+/// destinations are now on one of them, disabling those with a confirmed
+/// hit.
+pub fn spawn_recheck_job(db: DatabasePool, policy: DestinationPolicy) {
+    if policy.blocklists().is_empty() {
+        return;
+    }
+    let minutes = match std::env::var("BLOCKLIST_RECHECK_MINUTES") {
+        Ok(value) => match value.trim().parse::<u64>() {
+            Ok(minutes) => minutes,
+            Err(_) => {
+                tracing::warn!(
+                    "Invalid BLOCKLIST_RECHECK_MINUTES {:?}, using {}",
+                    value,
+                    DEFAULT_RECHECK_MINUTES
+                );
+                DEFAULT_RECHECK_MINUTES
+            }
+        },
+        Err(_) => DEFAULT_RECHECK_MINUTES,
+    };
+    if minutes == 0 {
+        tracing::info!("Blocklist re-checks are off, existing links are not re-checked");
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(minutes * 60));
+        loop {
+            interval.tick().await;
+            let blocklists = policy.blocklists().clone();
+            if let Err(err) = tokio::task::spawn_blocking(move || blocklists.reload()).await {
+                tracing::error!("Failed to reload blocklists: {}", err);
+            }
+            match policy.reflag_links(&db, None).await {
+                Ok(summary) if summary.disabled > 0 => tracing::warn!(
+                    "Disabled {} links whose destinations are on a blocklist",
+                    summary.disabled
+                ),
+                Ok(_) => {}
+                Err(err) => tracing::error!("Failed to re-check links: {}", err),
+            }
+        }
+    });
+}
+
+/// The host and all of its parent domains, `a.b.c`, `b.c` and `c`.
+fn parent_domains(host: &str) -> impl Iterator<Item = &str> {
+    std::iter::successors(Some(host), |host| {
+        host.split_once('.').map(|(_, rest)| rest)
+    })
+}
+
+/// The host suffix and path prefix combinations Safe Browsing hashes for a
+/// URL, such as `a.b.c/1/2.html?param=1`, `b.c/1/` and `b.c/`.
+fn url_expressions(url: &Url, host: &str) -> Vec<String> {
+    let mut hosts = vec![host.to_string()];
+    if host.parse::<IpAddr>().is_err() && !host.starts_with('[') {
+        let labels: Vec<&str> = host.split('.').collect();
+        if labels.len() > 2 {
+            for start in (labels.len().saturating_sub(5)).max(1)..labels.len() - 1 {
+                hosts.push(labels[start..].join("."));
+            }
+        }
+    }
+
+    let path = url.path();
+    let mut paths = Vec::new();
+    if let Some(query) = url.query() {
+        paths.push(format!("{}?{}", path, query));
+    }
+    paths.push(path.to_string());
+    let mut prefix = String::from("/");
+    paths.push(prefix.clone());
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    for segment in segments
+        .iter()
+        .take(segments.len().saturating_sub(1))
+        .take(3)
+    {
+        prefix.push_str(segment);
+        prefix.push('/');
+        paths.push(prefix.clone());
+    }
+    paths.sort();
+    paths.dedup();
+
+    hosts
+        .iter()
+        .flat_map(|host| paths.iter().map(move |path| format!("{}{}", host, path)))
+        .collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,241 @@
+//! Generation of short codes for links created without a custom code.
+//!
+//! `SHORT_CODE_STRATEGY` picks one of:
+//! - `random` (default): `SHORT_CODE_LENGTH` characters drawn from
+//!   `SHORT_CODE_ALPHABET`
+//! - `counter`: an instance-wide counter encoded with Sqids, so codes never
+//!   repeat and grow only as needed, but are at least `SHORT_CODE_LENGTH` long
+//! - `pronounceable`: alternating consonants and vowels, such as `bakotume`
+//! - `words`: `SHORT_CODE_LENGTH` words from a built-in list joined with
+//!   hyphens, such as `amber-otter-lamp`
+//!
+//! The generator only proposes codes. Callers skip codes that are already
+//! taken or retired, and the insert reserves the code atomically, asking for
+//! another one if it was taken in the meantime.
+
+use crate::models::DatabasePool;
+use anyhow::{anyhow, bail};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use sqids::Sqids;
+use sqlx::{PgConnection, SqliteConnection};
+
+const DEFAULT_ALPHABET: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+const CONSONANTS: &[u8] = b"bdfghjklmnprstvz";
+const VOWELS: &[u8] = b"aeiou";
+const MAX_LENGTH: usize = 32;
+/// Words are at most 6 letters, so 4 words and their hyphens fit in a code
+const MAX_WORDS: usize = 4;
+
+/// Short, common and unambiguous words for `words` codes. 256 of them, so
+/// each word adds 8 bits.
+const WORDS: [&str; 256] = [
+    "acorn", "actor", "agent", "alarm", "album", "amber", "angle", "apple", "apron", "arch",
+    "arena", "arrow", "atlas", "attic", "aunt", "award", "bacon", "badge", "bagel", "baker",
+    "bamboo", "banjo", "barn", "basil", "basin", "beach", "beard", "bench", "berry", "bird",
+    "bison", "blade", "blaze", "bloom", "board", "boat", "bonus", "book", "boot", "brave", "bread",
+    "brick", "bridge", "brook", "brush", "bucket", "bugle", "cabin", "cable", "cactus", "camel",
+    "camp", "candle", "canoe", "cargo", "carrot", "castle", "cedar", "chair", "chalk", "cherry",
+    "chess", "chief", "cider", "cinema", "circle", "citrus", "clay", "cliff", "clock", "cloud",
+    "clover", "coast", "cobra", "cocoa", "comet", "coral", "cotton", "crane", "crisp", "crown",
+    "cube", "daisy", "dance", "delta", "denim", "desert", "diary", "dingo", "dome", "donkey",
+    "dragon", "drum", "eagle", "echo", "elbow", "elder", "ember", "empire", "engine", "fable",
+    "falcon", "fancy", "farm", "feast", "fern", "ferry", "fiber", "field", "fig", "flame", "flute",
+    "forest", "fossil", "fox", "frost", "fudge", "gadget", "galaxy", "garden", "garlic", "gecko",
+    "giant", "ginger", "gravel", "globe", "glove", "goose", "grape", "gravy", "habit", "hammer",
+    "harbor", "hazel", "heron", "hollow", "honey", "hotel", "husky", "igloo", "island", "ivory",
+    "jacket", "jaguar", "jelly", "jewel", "jolly", "judge", "juice", "jungle", "kayak", "kettle",
+    "kiwi", "koala", "ladder", "lagoon", "lamp", "lemon", "lily", "linen", "lion", "lobby",
+    "lotus", "lucky", "lunar", "magnet", "mango", "maple", "marble", "meadow", "melon", "mint",
+    "mirror", "monkey", "moose", "mosaic", "motor", "mural", "nectar", "noble", "noodle", "nutmeg",
+    "oasis", "ocean", "olive", "onion", "opera", "orbit", "otter", "owl", "paddle", "panda",
+    "paper", "parrot", "pasta", "peach", "pearl", "pebble", "pepper", "piano", "pilot", "planet",
+    "plum", "pocket", "polar", "pony", "potato", "prism", "puffin", "quartz", "quiet", "quilt",
+    "rabbit", "radar", "radio", "raven", "ribbon", "river", "robin", "rocket", "rose", "ruby",
+    "saddle", "salad", "salmon", "sand", "scarf", "shell", "silver", "sketch", "sloth", "snow",
+    "sofa", "spark", "spice", "spoon", "squid", "stone", "sugar", "summit", "sunny", "swan",
+    "table", "tango", "tiger", "toast", "tomato", "topaz", "tulip", "turtle", "umbra", "unity",
+    "valley", "velvet", "violet", "walnut",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    Random,
+    Counter,
+    Pronounceable,
+    Words,
+}
+
+impl Strategy {
+    /// Pronounceable codes draw from far fewer combinations per character,
+    /// so they need to be longer to stay as hard to collide. For `words`
+    /// the length counts words rather than characters.
+    fn default_length(self) -> usize {
+        match self {
+            Strategy::Random | Strategy::Counter => 6,
+            Strategy::Pronounceable => 8,
+            Strategy::Words => 3,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CodeGenerator {
+    strategy: Strategy,
+    length: usize,
+    alphabet: Vec<char>,
+    sqids: Option<Sqids>,
+}
+
+impl CodeGenerator {
+    /// Reads `SHORT_CODE_STRATEGY`, `SHORT_CODE_LENGTH` and
+    /// `SHORT_CODE_ALPHABET`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let strategy = match std::env::var("SHORT_CODE_STRATEGY").as_deref() {
+            Err(_) | Ok("random") => Strategy::Random,
+            Ok("counter") => Strategy::Counter,
+            Ok("pronounceable") => Strategy::Pronounceable,
+            Ok("words") => Strategy::Words,
+            Ok(other) => bail!(
+                "Unknown SHORT_CODE_STRATEGY {:?}, expected random, counter, pronounceable or words",
+                other
+            ),
+        };
+
+        let length = match std::env::var("SHORT_CODE_LENGTH") {
+            Ok(value) => match value.trim().parse::<usize>() {
+                Ok(length) if (1..=MAX_LENGTH).contains(&length) => length,
+                _ => bail!("SHORT_CODE_LENGTH must be between 1 and {}", MAX_LENGTH),
+            },
+            Err(_) => strategy.default_length(),
+        };
+        if strategy == Strategy::Words && length > MAX_WORDS {
+            bail!("SHORT_CODE_LENGTH can be at most {} words", MAX_WORDS);
+        }
+
+        let alphabet: Vec<char> = std::env::var("SHORT_CODE_ALPHABET")
+            .unwrap_or_else(|_| DEFAULT_ALPHABET.to_string())
+            .chars()
+            .collect();
+        if alphabet.len() < 3 {
+            bail!("SHORT_CODE_ALPHABET needs at least 3 characters");
+        }
+        if let Some(c) = alphabet
+            .iter()
+            .find(|c| !c.is_ascii_alphanumeric() && **c != '_' && **c != '-')
+        {
+            bail!(
+                "SHORT_CODE_ALPHABET can only contain letters, numbers, underscores and hyphens, found {:?}",
+                c
+            );
+        }
+
+        let sqids = match strategy {
+            Strategy::Counter => Some(
+                Sqids::builder()
+                    .alphabet(alphabet.clone())
+                    .min_length(length as u8)
+                    .build()
+                    .map_err(|e| anyhow!("Invalid SHORT_CODE_ALPHABET: {}", e))?,
+            ),
+            _ => None,
+        };
+
+        tracing::info!("Generating {:?} short codes of length {}", strategy, length);
+        Ok(Self {
+            strategy,
+            length,
+            alphabet,
+            sqids,
+        })
+    }
+
+    /// Proposes a new code. It may already be in use.
+    pub async fn candidate(&self, db: &DatabasePool) -> Result<String, sqlx::Error> {
+        match db {
+            DatabasePool::Postgres(pool) => self.candidate_pg(&mut *pool.acquire().await?).await,
+            DatabasePool::Sqlite(pool) => self.candidate_sqlite(&mut *pool.acquire().await?).await,
+        }
+    }
+
+    /// Like `candidate`, drawing counter values on `conn` so it can be used
+    /// inside the transaction that inserts the link.
+    pub async fn candidate_pg(&self, conn: &mut PgConnection) -> Result<String, sqlx::Error> {
+        if let Some(code) = self.random_code() {
+            return Ok(code);
+        }
+        loop {
+            if let Some(code) = self.encode_counter(next_counter_pg(conn).await?) {
+                return Ok(code);
+            }
+        }
+    }
+
+    /// Like `candidate`, drawing counter values on `conn` so it can be used
+    /// inside the transaction that inserts the link.
+    pub async fn candidate_sqlite(
+        &self,
+        conn: &mut SqliteConnection,
+    ) -> Result<String, sqlx::Error> {
+        if let Some(code) = self.random_code() {
+            return Ok(code);
+        }
+        loop {
+            if let Some(code) = self.encode_counter(next_counter_sqlite(conn).await?) {
+                return Ok(code);
+            }
+        }
+    }
+
+    /// A code for the strategies that don't need the database.
+    fn random_code(&self) -> Option<String> {
+        let mut rng = rand::thread_rng();
+        match self.strategy {
+            Strategy::Random => Some(
+                (0..self.length)
+                    .map(|_| *self.alphabet.choose(&mut rng).unwrap())
+                    .collect(),
+            ),
+            Strategy::Pronounceable => Some(
+                (0..self.length)
+                    .map(|i| {
+                        let letters = if i % 2 == 0 { CONSONANTS } else { VOWELS };
+                        letters[rng.gen_range(0..letters.len())] as char
+                    })
+                    .collect(),
+            ),
+            Strategy::Words => Some(
+                (0..self.length)
+                    .map(|_| *WORDS.choose(&mut rng).unwrap())
+                    .collect::<Vec<_>>()
+                    .join("-"),
+            ),
+            Strategy::Counter => None,
+        }
+    }
+
+    /// Sqids refuses a few numbers whose codes would spell out blocked
+    /// words, those are simply skipped.
+    fn encode_counter(&self, value: i64) -> Option<String> {
+        let sqids = self.sqids.as_ref().expect("counter strategy without Sqids");
+        sqids.encode(&[value as u64]).ok()
+    }
+}
+
+async fn next_counter_pg(conn: &mut PgConnection) -> Result<i64, sqlx::Error> {
+    let (value,) = sqlx::query_as::<_, (i64,)>(
+        "UPDATE short_code_counter SET value = value + 1 WHERE id = 1 RETURNING value",
+    )
+    .fetch_one(conn)
+    .await?;
+    Ok(value)
+}
+
+async fn next_counter_sqlite(conn: &mut SqliteConnection) -> Result<i64, sqlx::Error> {
+    let (value,) = sqlx::query_as::<_, (i64,)>(
+        "UPDATE short_code_counter SET value = value + 1 WHERE id = 1 RETURNING value",
+    )
+    .fetch_one(conn)
+    .await?;
+    Ok(value)
+}
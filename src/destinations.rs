@@ -0,0 +1,517 @@
+//! Validation of link destinations. Every URL a link is created or edited
+//! with goes through `DestinationPolicy::check`, which parses it, rejects
+//! anything that isn't a plain http(s) URL to a well-formed host, and
+//! returns the normalized form that gets stored. Destinations also have to
+//! pass the domain rules admins set up for the whole server or for single
+//! users, see `DomainRules`, and must not be on one of the `blocklists`.
+
+use crate::blocklists::Blocklists;
+use crate::error::AppError;
+use crate::handlers::validate_custom_code;
+use crate::metadata::is_public_ip;
+use crate::models::{DatabasePool, DomainRule, RuleAction};
+use futures::stream::{self, StreamExt};
+use reqwest::Url;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::time::Duration;
+
+const MAX_HOST_LEN: usize = 253;
+const MAX_LABEL_LEN: usize = 63;
+const DNS_TIMEOUT: Duration = Duration::from_secs(2);
+/// How many host names `resolve_hosts` looks up at a time
+const MAX_CONCURRENT_LOOKUPS: usize = 8;
+
+#[derive(Clone, Default)]
+pub struct DestinationPolicy {
+    /// Hosts this instance serves short links on, lowercase, optionally with
+    /// a port
+    own_hosts: Vec<String>,
+    /// Where short links are published, from the first of `own_hosts`
+    base_url: Option<String>,
+    /// Reject loopback, private and link-local destinations
+    block_private: bool,
+    blocklists: Blocklists,
+}
+
+/// Host names looked up ahead of checking many URLs, with whether they
+/// resolve to an address that isn't public. See
+/// `DestinationPolicy::resolve_hosts`.
+#[derive(Default)]
+pub struct ResolvedHosts(HashMap<String, bool>);
+
+/// The outcome of `DestinationPolicy::reflag_links`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReflagSummary {
+    /// Links that are flagged now
+    pub flagged: u64,
+    /// Links that were disabled because they have a confirmed blocklist hit
+    pub disabled: u64,
+}
+
+impl DestinationPolicy {
+    /// Reads `SHORT_LINK_HOSTS` (comma-separated, each optionally starting
+    /// with `http://` or `https://`), `BLOCK_PRIVATE_DESTINATIONS` and the
+    /// blocklists.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let entries: Vec<String> = std::env::var("SHORT_LINK_HOSTS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|host| host.trim().trim_end_matches('/').to_lowercase())
+            .filter(|host| !host.is_empty())
+            .collect();
+        let base_url = entries.first().map(|entry| {
+            if entry.starts_with("http://") || entry.starts_with("https://") {
+                entry.clone()
+            } else {
+                format!("https://{}", entry)
+            }
+        });
+        let own_hosts = entries
+            .iter()
+            .map(|entry| {
+                let host = entry.strip_prefix("http://").unwrap_or(entry);
+                host.strip_prefix("https://").unwrap_or(host).to_string()
+            })
+            .collect();
+        let block_private = std::env::var("BLOCK_PRIVATE_DESTINATIONS").is_ok_and(|v| v == "true");
+
+        Ok(Self {
+            own_hosts,
+            base_url,
+            block_private,
+            blocklists: Blocklists::from_env()?,
+        })
+    }
+
+    /// The configured scheme and host of short links, such as
+    /// `https://sho.rt`, if `SHORT_LINK_HOSTS` is set.
+    pub fn base_url(&self) -> Option<&str> {
+        self.base_url.as_deref()
+    }
+
+    pub fn blocklists(&self) -> &Blocklists {
+        &self.blocklists
+    }
+
+    /// Validates a destination for one of `user_id`'s links and returns it
+    /// normalized: lowercase scheme and host, IDN hosts in their ASCII form,
+    /// no default port, and at least `/` as the path.
+    pub async fn check(
+        &self,
+        db: &DatabasePool,
+        user_id: i32,
+        url: &str,
+    ) -> Result<String, AppError> {
+        let rules = DomainRules::load(db, Some(user_id)).await?;
+        self.check_with_rules(&rules, &ResolvedHosts::default(), user_id, url)
+            .await
+    }
+
+    /// `check` with rules loaded and host names resolved up front, for
+    /// checking many URLs at once. Hosts missing from `resolved` are looked
+    /// up as they come.
+    pub async fn check_with_rules(
+        &self,
+        rules: &DomainRules,
+        resolved: &ResolvedHosts,
+        user_id: i32,
+        url: &str,
+    ) -> Result<String, AppError> {
+        let url = url.trim();
+        if url.is_empty() {
+            return Err(invalid("URL cannot be empty"));
+        }
+        let parsed =
+            Url::parse(url).map_err(|e| AppError::InvalidInput(format!("Invalid URL: {}", e)))?;
+
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(invalid("URL must start with http:// or https://"));
+        }
+        if !parsed.username().is_empty() || parsed.password().is_some() {
+            return Err(invalid("URL must not contain a username or password"));
+        }
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| invalid("URL must have a host"))?;
+
+        match parse_ip(host) {
+            Some(ip) => {
+                if self.block_private && !is_public_ip(ip) {
+                    return Err(private_destination());
+                }
+            }
+            None => {
+                check_domain(host)?;
+                if self.block_private {
+                    check_resolves_public(host, resolved).await?;
+                }
+            }
+        }
+
+        if self.points_at_short_link(host, &parsed) {
+            return Err(invalid(
+                "URL points at a short link on this server, which would redirect in a loop",
+            ));
+        }
+        if let Some(reason) = rules.violation(Some(user_id), host) {
+            return Err(AppError::InvalidInput(reason));
+        }
+        if let Some(hit) = self.blocklists.hit(&parsed) {
+            return Err(AppError::InvalidInput(hit.reason));
+        }
+
+        Ok(parsed.into())
+    }
+
+    /// Looks up the distinct host names of `urls` once each, a few at a
+    /// time, for `check_with_rules`. Nothing is looked up unless private
+    /// destinations are blocked.
+    pub async fn resolve_hosts<'a>(
+        &self,
+        urls: impl IntoIterator<Item = &'a str>,
+    ) -> ResolvedHosts {
+        if !self.block_private {
+            return ResolvedHosts::default();
+        }
+        let hosts: HashSet<String> = urls
+            .into_iter()
+            .filter_map(|url| Url::parse(url.trim()).ok())
+            .filter_map(|url| {
+                let host = url.host_str()?.trim_end_matches('.');
+                (parse_ip(host).is_none() && check_domain(host).is_ok()).then(|| host.to_string())
+            })
+            .collect();
+
+        let resolved = stream::iter(hosts)
+            .map(|host| async move {
+                let private = resolves_private(&host).await;
+                (host, private)
+            })
+            .buffer_unordered(MAX_CONCURRENT_LOOKUPS)
+            .collect()
+            .await;
+        ResolvedHosts(resolved)
+    }
+
+    /// Whether the URL is `https://<one of our hosts>/<code>`, which would
+    /// send visitors round in circles once that code exists.
+    fn points_at_short_link(&self, host: &str, url: &Url) -> bool {
+        let host = host.trim_end_matches('.');
+        let with_port = match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+        if !self
+            .own_hosts
+            .iter()
+            .any(|own| *own == host || *own == with_port)
+        {
+            return false;
+        }
+
+        let path = url.path().trim_start_matches('/').trim_end_matches('/');
+        validate_custom_code(path).is_ok()
+    }
+
+    /// Runs `reflag_links` in the background, for requests that change the
+    /// rules and shouldn't wait for every link to be checked.
+    pub fn spawn_reflag(&self, db: &DatabasePool, user_id: Option<i32>) {
+        let policy = self.clone();
+        let db = db.clone();
+        tokio::spawn(async move {
+            match policy.reflag_links(&db, user_id).await {
+                Ok(summary) => tracing::info!(
+                    "Re-checked links after a domain rule change, {} flagged",
+                    summary.flagged
+                ),
+                Err(err) => tracing::error!("Failed to re-check links: {}", err),
+            }
+        });
+    }
+
+    /// Re-checks the destinations of links against the current domain rules
+    /// and blocklists, flagging links that break one and clearing the flag of
+    /// links that no longer do. Links with a new confirmed blocklist hit are
+    /// also disabled, the way an admin disables a link, while a hash prefix
+    /// match only flags the link for review. Only `user_id`'s links are
+    /// checked if given.
+    pub async fn reflag_links(
+        &self,
+        db: &DatabasePool,
+        user_id: Option<i32>,
+    ) -> Result<ReflagSummary, sqlx::Error> {
+        let rules = DomainRules::load(db, None).await?;
+        let links = match db {
+            DatabasePool::Postgres(pool) => {
+                sqlx::query_as::<_, (i32, Option<i32>, String, Option<String>)>(
+                    r#"
+                    SELECT id, user_id, original_url, flag_reason FROM links
+                    WHERE deleted_at IS NULL AND ($1::INTEGER IS NULL OR user_id = $1)
+                    "#,
+                )
+                .bind(user_id)
+                .fetch_all(pool)
+                .await?
+            }
+            DatabasePool::Sqlite(pool) => {
+                sqlx::query_as::<_, (i32, Option<i32>, String, Option<String>)>(
+                    r#"
+                    SELECT id, user_id, original_url, flag_reason FROM links
+                    WHERE deleted_at IS NULL AND (?1 IS NULL OR user_id = ?1)
+                    "#,
+                )
+                .bind(user_id)
+                .fetch_all(pool)
+                .await?
+            }
+        };
+
+        let mut summary = ReflagSummary::default();
+        for (id, owner, url, current) in links {
+            let Ok(url) = Url::parse(&url) else {
+                continue;
+            };
+            let blocked = self.blocklists.hit(&url);
+            let disable = blocked.as_ref().is_some_and(|hit| hit.confirmed);
+            let reason = blocked
+                .map(|hit| hit.reason)
+                .or_else(|| url.host_str().and_then(|host| rules.violation(owner, host)));
+            if reason.is_some() {
+                summary.flagged += 1;
+            }
+            if reason == current {
+                continue;
+            }
+            if disable {
+                summary.disabled += 1;
+            }
+
+            match db {
+                DatabasePool::Postgres(pool) => {
+                    sqlx::query(
+                        r#"
+                        UPDATE links
+                        SET flag_reason = $1,
+                            flagged_at = CASE WHEN $1 IS NULL THEN NULL ELSE NOW() END,
+                            enabled = CASE WHEN $3 THEN FALSE ELSE enabled END,
+                            disabled_at = CASE WHEN $3 THEN NOW() ELSE disabled_at END,
+                            disabled_by = CASE WHEN $3 THEN NULL ELSE disabled_by END,
+                            disabled_reason = CASE WHEN $3 THEN $1 ELSE disabled_reason END,
+                            version = CASE WHEN $3 THEN version + 1 ELSE version END
+                        WHERE id = $2
+                        "#,
+                    )
+                    .bind(&reason)
+                    .bind(id)
+                    .bind(disable)
+                    .execute(pool)
+                    .await?;
+                }
+                DatabasePool::Sqlite(pool) => {
+                    sqlx::query(
+                        r#"
+                        UPDATE links
+                        SET flag_reason = ?1,
+                            flagged_at = CASE WHEN ?1 IS NULL THEN NULL ELSE CURRENT_TIMESTAMP END,
+                            enabled = CASE WHEN ?3 THEN FALSE ELSE enabled END,
+                            disabled_at = CASE WHEN ?3 THEN CURRENT_TIMESTAMP ELSE disabled_at END,
+                            disabled_by = CASE WHEN ?3 THEN NULL ELSE disabled_by END,
+                            disabled_reason = CASE WHEN ?3 THEN ?1 ELSE disabled_reason END,
+                            version = CASE WHEN ?3 THEN version + 1 ELSE version END
+                        WHERE id = ?2
+                        "#,
+                    )
+                    .bind(&reason)
+                    .bind(id)
+                    .bind(disable)
+                    .execute(pool)
+                    .await?;
+                }
+            }
+        }
+        Ok(summary)
+    }
+}
+
+/// Allow and deny rules for destination hosts. A host is refused if any
+/// deny rule matches it. If there are allow rules, it also has to match one
+/// of them: the server-wide ones, and the user's own if they have any.
+pub struct DomainRules {
+    rules: Vec<DomainRule>,
+}
+
+impl DomainRules {
+    /// Loads the server-wide rules and `user_id`'s, or every rule with `None`.
+    pub async fn load(db: &DatabasePool, user_id: Option<i32>) -> Result<Self, sqlx::Error> {
+        let rules = match db {
+            DatabasePool::Postgres(pool) => {
+                sqlx::query_as::<_, DomainRule>(
+                    r#"
+                    SELECT * FROM domain_rules
+                    WHERE $1::INTEGER IS NULL OR user_id IS NULL OR user_id = $1
+                    "#,
+                )
+                .bind(user_id)
+                .fetch_all(pool)
+                .await?
+            }
+            DatabasePool::Sqlite(pool) => {
+                sqlx::query_as::<_, DomainRule>(
+                    r#"
+                    SELECT * FROM domain_rules
+                    WHERE ?1 IS NULL OR user_id IS NULL OR user_id = ?1
+                    "#,
+                )
+                .bind(user_id)
+                .fetch_all(pool)
+                .await?
+            }
+        };
+        Ok(Self { rules })
+    }
+
+    /// Why links of `user_id` to `host` aren't allowed, if they aren't.
+    pub fn violation(&self, user_id: Option<i32>, host: &str) -> Option<String> {
+        let host = host.trim_end_matches('.');
+        let applies = |rule: &&DomainRule| rule.user_id.is_none() || rule.user_id == user_id;
+
+        if self.rules.iter().filter(applies).any(|rule| {
+            rule.action == RuleAction::Deny.as_str() && pattern_matches(&rule.pattern, host)
+        }) {
+            return Some(format!("Links to {} are blocked", host));
+        }
+
+        let scopes = if user_id.is_some() {
+            vec![None, user_id]
+        } else {
+            vec![None]
+        };
+        for scope in scopes {
+            let mut allowed = self
+                .rules
+                .iter()
+                .filter(|rule| rule.user_id == scope && rule.action == RuleAction::Allow.as_str())
+                .peekable();
+            if allowed.peek().is_some() && !allowed.any(|rule| pattern_matches(&rule.pattern, host))
+            {
+                return Some(format!("Links to {} are not on the allowlist", host));
+            }
+        }
+        None
+    }
+}
+
+/// Normalizes a rule pattern: a host name, or `*.` and a domain to match
+/// all of its subdomains. Internationalized names are stored in ASCII.
+pub fn normalize_pattern(pattern: &str) -> Result<String, AppError> {
+    let pattern = pattern.trim().to_lowercase();
+    let (wildcard, domain) = match pattern.strip_prefix("*.") {
+        Some(domain) => (true, domain),
+        None => (false, pattern.as_str()),
+    };
+    let invalid_pattern = || {
+        invalid(
+            "Pattern must be a host name such as example.com, or *.example.com for its subdomains",
+        )
+    };
+    if domain.is_empty() || domain.contains(['*', '/', ':', '@', '?', '#', '[', ']']) {
+        return Err(invalid_pattern());
+    }
+
+    let host = Url::parse(&format!("http://{}/", domain))
+        .ok()
+        .and_then(|url| {
+            url.host_str()
+                .map(|host| host.trim_end_matches('.').to_string())
+        })
+        .ok_or_else(invalid_pattern)?;
+    if host.parse::<IpAddr>().is_err() {
+        check_domain(&host).map_err(|_| invalid_pattern())?;
+    } else if wildcard {
+        return Err(invalid_pattern());
+    }
+
+    Ok(if wildcard {
+        format!("*.{}", host)
+    } else {
+        host
+    })
+}
+
+fn pattern_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+        None => host == pattern,
+    }
+}
+
+fn parse_ip(host: &str) -> Option<IpAddr> {
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
+
+/// Checks a hostname, already converted to ASCII by the URL parser, label
+/// by label.
+fn check_domain(host: &str) -> Result<(), AppError> {
+    let host = host.strip_suffix('.').unwrap_or(host);
+    if host.is_empty() || host.len() > MAX_HOST_LEN {
+        return Err(malformed_host());
+    }
+    for label in host.split('.') {
+        let valid = !label.is_empty()
+            && label.len() <= MAX_LABEL_LEN
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(malformed_host());
+        }
+    }
+    Ok(())
+}
+
+fn invalid(message: &str) -> AppError {
+    AppError::InvalidInput(message.to_string())
+}
+
+fn malformed_host() -> AppError {
+    invalid("URL has a malformed host name")
+}
+
+async fn check_resolves_public(host: &str, resolved: &ResolvedHosts) -> Result<(), AppError> {
+    let host = host.trim_end_matches('.');
+    if host == "localhost" || host.ends_with(".localhost") {
+        return Err(private_destination());
+    }
+    let private = match resolved.0.get(host) {
+        Some(&private) => private,
+        None => resolves_private(host).await,
+    };
+    if private {
+        return Err(private_destination());
+    }
+    Ok(())
+}
+
+/// Whether `host` resolves to an address that isn't public. A name that
+/// doesn't resolve right now is let through, only names that are known to
+/// lead somewhere internal are refused.
+async fn resolves_private(host: &str) -> bool {
+    // The port doesn't change the addresses, but the lookup needs one
+    let lookup = tokio::time::timeout(DNS_TIMEOUT, tokio::net::lookup_host((host, 80)));
+    match lookup.await {
+        Ok(Ok(mut addrs)) => addrs.any(|addr| !is_public_ip(addr.ip())),
+        _ => false,
+    }
+}
+
+fn private_destination() -> AppError {
+    invalid("URL must not point at a loopback, private or link-local address")
+}
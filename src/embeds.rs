@@ -0,0 +1,188 @@
+//! SVG images of a link's clicks for embedding in READMEs and wiki pages:
+//! a shields.io style badge with the total and a sparkline of daily clicks.
+//! They are public, but only served for links whose owner made them
+//! `embeddable`.
+
+use crate::error::AppError;
+use crate::models::{ClickStats, SparklineQuery};
+use crate::pages::escape_html;
+use crate::text::parse_hex_color;
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+use chrono::NaiveDate;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// How long caches may keep an image before checking its `ETag` again
+const MAX_AGE: &str = "public, max-age=300";
+const MAX_LABEL_LEN: usize = 32;
+const DEFAULT_BADGE_COLOR: [u8; 4] = [0x44, 0xcc, 0x11, 0xff];
+const DEFAULT_LINE_COLOR: [u8; 4] = [0x25, 0x63, 0xeb, 0xff];
+const DEFAULT_DAYS: u32 = 30;
+const MAX_DAYS: u32 = 365;
+
+/// A two-part badge such as `clicks | 1.2k`.
+pub fn badge(label: Option<&str>, color: Option<&str>, clicks: i64) -> Result<String, AppError> {
+    let label = label.map(str::trim).unwrap_or("clicks");
+    if label.is_empty() || label.chars().count() > MAX_LABEL_LEN {
+        return Err(AppError::InvalidInput(format!(
+            "Label must be 1 to {} characters long",
+            MAX_LABEL_LEN
+        )));
+    }
+    let fill = svg_paint("fill", color_or(color, DEFAULT_BADGE_COLOR)?);
+    let value = format_count(clicks);
+
+    let (label_width, value_width) = (text_width(label) + 10, text_width(&value) + 10);
+    let width = label_width + value_width;
+    let (label, value) = (escape_html(label), escape_html(&value));
+    Ok(format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {value}"><title>{label}: {value}</title><linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient><clipPath id="r"><rect width="{width}" height="20" rx="3" fill="#fff"/></clipPath><g clip-path="url(#r)"><rect width="{label_width}" height="20" fill="#555"/><rect x="{label_width}" width="{value_width}" height="20" {fill}/><rect width="{width}" height="20" fill="url(#s)"/></g><g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11"><text x="{label_x}" y="15" fill="#010101" fill-opacity=".3">{label}</text><text x="{label_x}" y="14">{label}</text><text x="{value_x}" y="15" fill="#010101" fill-opacity=".3">{value}</text><text x="{value_x}" y="14">{value}</text></g></svg>"##,
+        width = width,
+        label_width = label_width,
+        value_width = value_width,
+        fill = fill,
+        label = label,
+        value = value,
+        label_x = label_width as f64 / 2.0,
+        value_x = label_width as f64 + value_width as f64 / 2.0,
+    ))
+}
+
+/// A line of the clicks per day over the `days` days up to `today`.
+pub fn sparkline(
+    query: &SparklineQuery,
+    clicks: &[ClickStats],
+    today: NaiveDate,
+) -> Result<String, AppError> {
+    let days = query.days.unwrap_or(DEFAULT_DAYS);
+    if !(2..=MAX_DAYS).contains(&days) {
+        return Err(AppError::InvalidInput(format!(
+            "Days must be between 2 and {}",
+            MAX_DAYS
+        )));
+    }
+    let width = query.width.unwrap_or(120);
+    let height = query.height.unwrap_or(30);
+    if !(20..=1000).contains(&width) || !(10..=500).contains(&height) {
+        return Err(AppError::InvalidInput(
+            "Width must be between 20 and 1000 pixels and height between 10 and 500".to_string(),
+        ));
+    }
+    let stroke = svg_paint(
+        "stroke",
+        color_or(query.color.as_deref(), DEFAULT_LINE_COLOR)?,
+    );
+
+    let by_date: HashMap<&str, i64> = clicks
+        .iter()
+        .map(|day| (day.date.as_str(), day.clicks))
+        .collect();
+    let counts: Vec<i64> = (0..days)
+        .rev()
+        .map(|ago| {
+            let date = today - chrono::Duration::days(ago as i64);
+            let date = date.format("%Y-%m-%d").to_string();
+            by_date.get(date.as_str()).copied().unwrap_or(0)
+        })
+        .collect();
+    let total: i64 = counts.iter().sum();
+    let max = counts.iter().copied().max().unwrap_or(0).max(1) as f64;
+
+    // Keep the line's stroke inside the image
+    let pad = 1.5;
+    let (w, h) = (width as f64 - 2.0 * pad, height as f64 - 2.0 * pad);
+    let mut points = String::new();
+    for (i, count) in counts.iter().enumerate() {
+        let x = pad + w * i as f64 / (days - 1) as f64;
+        let y = pad + h - h * *count as f64 / max;
+        let _ = write!(points, "{:.1},{:.1} ", x, y);
+    }
+
+    let title = format!("{} clicks in the last {} days", total, days);
+    Ok(format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" role="img" aria-label="{title}"><title>{title}</title><polyline fill="none" {stroke} stroke-width="1.5" stroke-linejoin="round" stroke-linecap="round" points="{points}"/></svg>"#,
+        width = width,
+        height = height,
+        title = title,
+        stroke = stroke,
+        points = points.trim_end(),
+    ))
+}
+
+/// Sends an SVG with an `ETag`, answering `If-None-Match` with
+/// `304 Not Modified`.
+pub fn svg_response(req: &HttpRequest, svg: String) -> HttpResponse {
+    let hash = Sha256::digest(svg.as_bytes());
+    let etag = format!(
+        "\"{}\"",
+        hash[..8]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    );
+    let cached = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag));
+
+    let mut response = if cached {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::CACHE_CONTROL, MAX_AGE));
+    if cached {
+        response.finish()
+    } else {
+        response.content_type("image/svg+xml").body(svg)
+    }
+}
+
+/// `1234` as `1.2k`, `2500000` as `2.5M`.
+fn format_count(count: i64) -> String {
+    match count {
+        ..1_000 => count.to_string(),
+        // 999950 would round up to 1000.0k
+        1_000..999_950 => format!("{:.1}k", count as f64 / 1e3).replace(".0k", "k"),
+        _ => format!("{:.1}M", count as f64 / 1e6).replace(".0M", "M"),
+    }
+}
+
+/// Roughly how wide `text` is in 11px Verdana, the badge font.
+fn text_width(text: &str) -> u32 {
+    let width: f64 = text
+        .chars()
+        .map(|c| match c {
+            'i' | 'l' | 'j' | '.' | ',' | ':' | ';' | '|' | '!' | '\'' => 3.5,
+            ' ' | 'f' | 'r' | 't' | '(' | ')' | '[' | ']' => 4.5,
+            'm' | 'w' => 9.5,
+            'M' | 'W' => 11.0,
+            c if c.is_ascii_uppercase() => 7.5,
+            _ => 7.0,
+        })
+        .sum();
+    width.ceil() as u32
+}
+
+/// The `color` parameter, or `default` if it's missing.
+fn color_or(value: Option<&str>, default: [u8; 4]) -> Result<[u8; 4], AppError> {
+    match value {
+        Some(value) => parse_hex_color("Color", value),
+        None => Ok(default),
+    }
+}
+
+/// An SVG paint attribute such as `fill` for a colour, with an opacity
+/// attribute for a translucent one.
+pub(crate) fn svg_paint(attribute: &str, [r, g, b, a]: [u8; 4]) -> String {
+    let mut paint = format!(r##"{}="#{:02x}{:02x}{:02x}""##, attribute, r, g, b);
+    if a < 255 {
+        let _ = write!(paint, r#" {}-opacity="{:.3}""#, attribute, a as f64 / 255.0);
+    }
+    paint
+}
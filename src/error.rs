@@ -17,6 +17,18 @@ pub enum AppError {
     
     #[error("Unauthorized")]
     Unauthorized,
+
+    #[error("Forbidden")]
+    Forbidden,
+
+    #[error("Precondition failed")]
+    PreconditionFailed,
+
+    #[error("Banned")]
+    Banned,
+
+    #[error("Too many requests")]
+    TooManyRequests,
 }
 
 impl ResponseError for AppError {
@@ -27,6 +39,13 @@ impl ResponseError for AppError {
             AppError::InvalidInput(msg) => HttpResponse::BadRequest().json(msg),
             AppError::Auth(msg) => HttpResponse::BadRequest().json(msg),
             AppError::Unauthorized => HttpResponse::Unauthorized().json("Unauthorized"),
+            AppError::Forbidden => HttpResponse::Forbidden().json("Forbidden"),
+            AppError::PreconditionFailed => HttpResponse::PreconditionFailed()
+                .json("The link was changed since it was fetched"),
+            AppError::Banned => HttpResponse::Forbidden().json("This account has been banned"),
+            AppError::TooManyRequests => {
+                HttpResponse::TooManyRequests().json("Too many requests, try again later")
+            }
         }
     }
 }
\ No newline at end of file
@@ -1,9 +1,24 @@
 use crate::auth::AuthenticatedUser;
+use crate::codes::CodeGenerator;
+use crate::destinations::{self, DomainRules};
+use crate::embeds;
+use crate::importers;
+use crate::pages::{self, Preview, StatsPage, Unfurl};
+use crate::qr::QrStyle;
+use crate::reports;
+use crate::text::{MAX_DESCRIPTION_LEN, MAX_TITLE_LEN};
 use crate::{
     error::AppError,
     models::{
-        AuthResponse, Claims, ClickStats, CreateLink, DatabasePool, Link, LoginRequest,
-        RegisterRequest, SourceStats, User, UserResponse,
+        AbuseReport, AliasRequest, AuthResponse, BadgeQuery, BulkCreateResponse, BulkLinkResult,
+        BulkMode, BulkQuery, BulkStatus, Claims, ClickStats, CodeTombstone, ConflictStrategy,
+        CreateLink, DatabasePool, DomainRule, DomainRuleRequest, ExportFormat, ExportQuery,
+        ExportedLink, ImportFormat, ImportQuery, ImportResponse, ImportResult, ImportStatus, Link,
+        LinkCode, LinkExport, LinkListQuery, LinkPage, LinkRevision, LinkSearchResult, LinkSort,
+        LoginRequest, ModerationRequest, PublicStats, PublicStatsQuery, QrQuery, RegisterRequest,
+        ReportChallenge, ReportListQuery, ReportRequest, ReportStatusFilter, SearchQuery,
+        SortOrder, SourceStats, SparklineQuery, StatsFormat, StatsShare, StatsShareClaims, Tag,
+        TagRequest, TagSummary, UpdateLink, UpdateProfile, User, UserResponse,
     },
     AppState,
 };
@@ -13,14 +28,19 @@ use argon2::{
     PasswordVerifier,
 };
 use argon2::{Argon2, PasswordHash, PasswordHasher};
-use jsonwebtoken::{encode, EncodingKey, Header};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use sqlx::{Postgres, Sqlite};
+use sqlx::{PgConnection, Postgres, Sqlite, SqliteConnection};
+use std::collections::{HashMap, HashSet};
 
 lazy_static! {
     static ref VALID_CODE_REGEX: Regex = Regex::new(r"^[a-zA-Z0-9_-]{1,32}$").unwrap();
+    static ref VALID_TAG_REGEX: Regex = Regex::new(r"^[\w .-]+(/[\w .-]+)*$").unwrap();
 }
 
 pub async fn create_short_url(
@@ -29,229 +49,847 @@ pub async fn create_short_url(
     payload: web::Json<CreateLink>,
 ) -> Result<impl Responder, AppError> {
     tracing::debug!("Creating short URL with user_id: {}", user.user_id);
-    validate_url(&payload.url)?;
 
-    let short_code = if let Some(ref custom_code) = payload.custom_code {
-        validate_custom_code(custom_code)?;
+    let url = state
+        .destinations
+        .check(&state.db, user.user_id, &payload.url)
+        .await?;
 
-        // Check if code exists using match on pool type
-        let exists = match &state.db {
-            DatabasePool::Postgres(pool) => {
-                sqlx::query_as::<_, Link>("SELECT * FROM links WHERE short_code = $1")
-                    .bind(custom_code)
-                    .fetch_optional(pool)
-                    .await?
-            }
-            DatabasePool::Sqlite(pool) => {
-                sqlx::query_as::<_, Link>("SELECT * FROM links WHERE short_code = ?1")
-                    .bind(custom_code)
-                    .fetch_optional(pool)
-                    .await?
-            }
-        };
+    // Hand back the caller's existing link for the same destination, unless
+    // they asked for a particular code. The link is returned as it is, the
+    // title, tags and other fields of the request don't apply to it
+    let dedupe = payload.dedupe.unwrap_or(state.dedupe_links);
+    if dedupe && payload.custom_code.is_none() {
+        if let Some(mut link) = find_link_by_url(&state.db, user.user_id, &url).await? {
+            attach_tags(&state.db, vec![&mut link]).await?;
+            return Ok(HttpResponse::Ok()
+                .append_header(("ETag", link_etag(&link)))
+                .json(link));
+        }
+    }
+
+    let new_link = prepare_link(&state, user.user_id, url, &payload).await?;
+
+    // Start transaction based on pool type
+    let result = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            let mut tx = pool.begin().await?;
+            let link = insert_link_pg(&mut tx, &state.codes, user.user_id, &new_link).await?;
+            tx.commit().await?;
+            link
+        }
+        DatabasePool::Sqlite(pool) => {
+            let mut tx = pool.begin().await?;
+            let link = insert_link_sqlite(&mut tx, &state.codes, user.user_id, &new_link).await?;
+            tx.commit().await?;
+            link
+        }
+    };
+
+    if result.title.is_none() {
+        state.metadata.enqueue(result.id, &result.original_url);
+    }
+
+    Ok(HttpResponse::Created()
+        .append_header(("ETag", link_etag(&result)))
+        .json(result))
+}
+
+/// The user's oldest live, enabled link to `url`, which should be normalized. Links
+/// stored before destinations were normalized are matched as well.
+async fn find_link_by_url(
+    db: &DatabasePool,
+    user_id: i32,
+    url: &str,
+) -> Result<Option<Link>, AppError> {
+    // Older links may lack the `/` that normalizing adds to a bare host
+    let legacy = match url.strip_suffix('/') {
+        Some(bare) if bare.matches('/').count() == 2 => bare,
+        _ => url,
+    };
+
+    let link = match db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, Link>(
+                r#"
+                SELECT * FROM links
+                WHERE user_id = $1 AND deleted_at IS NULL AND enabled AND original_url IN ($2, $3)
+                ORDER BY created_at ASC, id ASC
+                LIMIT 1
+                "#,
+            )
+            .bind(user_id)
+            .bind(url)
+            .bind(legacy)
+            .fetch_optional(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, Link>(
+                r#"
+                SELECT * FROM links
+                WHERE user_id = ?1 AND deleted_at IS NULL AND enabled AND original_url IN (?2, ?3)
+                ORDER BY created_at ASC, id ASC
+                LIMIT 1
+                "#,
+            )
+            .bind(user_id)
+            .bind(url)
+            .bind(legacy)
+            .fetch_optional(pool)
+            .await?
+        }
+    };
+    Ok(link)
+}
+
+fn link_etag(link: &Link) -> String {
+    format!("\"{}\"", link.version)
+}
+
+/// Checks an `If-Match` header against the link's current version. A
+/// missing header or `*` matches anything.
+fn check_if_match(req: &HttpRequest, link: &Link) -> Result<(), AppError> {
+    let Some(header) = req.headers().get("If-Match") else {
+        return Ok(());
+    };
+    let header = header
+        .to_str()
+        .map_err(|_| AppError::InvalidInput("Invalid If-Match header".to_string()))?;
+    let etag = link_etag(link);
+    let matches = header
+        .split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
 
-        if exists.is_some() {
+    if matches {
+        Ok(())
+    } else {
+        Err(AppError::PreconditionFailed)
+    }
+}
+
+/// A link that has passed validation and is ready to be inserted.
+struct NewLink {
+    url: String,
+    short_code: String,
+    /// Whether `short_code` came from the generator, in which case another
+    /// one is drawn if it was taken by the time the link is inserted. An
+    /// empty generated code is only drawn then.
+    generated: bool,
+    source: Option<String>,
+    /// Set when importing links that already have a history elsewhere
+    created_at: Option<chrono::DateTime<chrono::Utc>>,
+    clicks: i64,
+    title: Option<String>,
+    description: Option<String>,
+    notes: Option<String>,
+    tags: Vec<String>,
+    preview: bool,
+    og_title: Option<String>,
+    og_description: Option<String>,
+    og_image: Option<String>,
+    embeddable: bool,
+    public_stats: bool,
+}
+
+/// Validates a `CreateLink` payload and settles on the short code to use.
+/// `url` is the payload's destination as `DestinationPolicy` checked it.
+async fn prepare_link(
+    state: &AppState,
+    user_id: i32,
+    url: String,
+    payload: &CreateLink,
+) -> Result<NewLink, AppError> {
+    let tags = normalize_tags(payload.tags.as_deref().unwrap_or_default())?;
+
+    let short_code = if let Some(ref custom_code) = payload.custom_code {
+        validate_custom_code(custom_code)?;
+        if code_taken(&state.db, custom_code).await? {
             return Err(AppError::InvalidInput(
                 "Custom code already taken".to_string(),
             ));
         }
+        if code_retired(&state.db, custom_code, user_id).await? {
+            return Err(AppError::InvalidInput(CODE_RETIRED_MESSAGE.to_string()));
+        }
         custom_code.clone()
     } else {
-        generate_short_code()
+        generate_short_code(state, user_id).await?
     };
 
-    // Start transaction based on pool type
-    let result = match &state.db {
-        DatabasePool::Postgres(pool) => {
-            let mut tx = pool.begin().await?;
+    Ok(NewLink {
+        url,
+        short_code,
+        generated: payload.custom_code.is_none(),
+        source: payload.source.clone(),
+        created_at: None,
+        clicks: 0,
+        title: normalize_text("Title", payload.title.as_deref(), MAX_TITLE_LEN)?,
+        description: normalize_text(
+            "Description",
+            payload.description.as_deref(),
+            MAX_DESCRIPTION_LEN,
+        )?,
+        notes: normalize_text("Notes", payload.notes.as_deref(), MAX_NOTES_LEN)?,
+        tags,
+        preview: payload.preview.unwrap_or(false),
+        og_title: normalize_text(
+            "Open Graph title",
+            payload.og_title.as_deref(),
+            MAX_TITLE_LEN,
+        )?,
+        og_description: normalize_text(
+            "Open Graph description",
+            payload.og_description.as_deref(),
+            MAX_DESCRIPTION_LEN,
+        )?,
+        og_image: normalize_og_image(payload.og_image.as_deref())?.filter(|v| !v.is_empty()),
+        embeddable: payload.embeddable.unwrap_or(false),
+        public_stats: payload.public_stats.unwrap_or(false),
+    })
+}
 
-            let link = sqlx::query_as::<_, Link>(
-                "INSERT INTO links (original_url, short_code, user_id) VALUES ($1, $2, $3) RETURNING *"
-            )
-            .bind(&payload.url)
-            .bind(&short_code)
-            .bind(user.user_id)
-            .fetch_one(&mut *tx)
-            .await?;
+async fn code_taken(db: &DatabasePool, code: &str) -> Result<bool, AppError> {
+    Ok(code_owner(db, code).await?.is_some())
+}
 
-            if let Some(ref source) = payload.source {
-                sqlx::query("INSERT INTO clicks (link_id, source) VALUES ($1, $2)")
-                    .bind(link.id)
-                    .bind(source)
-                    .execute(&mut *tx)
-                    .await?;
-            }
+const CODE_RETIRED_MESSAGE: &str = "This code belonged to a deleted link and can't be reused";
 
-            tx.commit().await?;
-            link
+/// Whether `code` has an active tombstone left by a link that `user_id`
+/// didn't own. Owners may always take their old codes back.
+async fn code_retired(db: &DatabasePool, code: &str, user_id: i32) -> Result<bool, AppError> {
+    let retired = match db {
+        DatabasePool::Postgres(pool) => {
+            code_retired_pg(&mut *pool.acquire().await?, code, user_id).await?
         }
         DatabasePool::Sqlite(pool) => {
-            let mut tx = pool.begin().await?;
+            code_retired_sqlite(&mut *pool.acquire().await?, code, user_id).await?
+        }
+    };
+    Ok(retired)
+}
 
-            let link = sqlx::query_as::<_, Link>(
-                "INSERT INTO links (original_url, short_code, user_id) VALUES (?1, ?2, ?3) RETURNING *"
-            )
-            .bind(&payload.url)
-            .bind(&short_code)
-            .bind(user.user_id)
-            .fetch_one(&mut *tx)
+async fn code_retired_pg(
+    conn: &mut PgConnection,
+    code: &str,
+    user_id: i32,
+) -> Result<bool, sqlx::Error> {
+    let tombstone = sqlx::query_as::<_, (String,)>(
+        r#"
+        SELECT short_code FROM code_tombstones
+        WHERE short_code = $1
+            AND user_id IS DISTINCT FROM $2
+            AND (expires_at IS NULL OR expires_at > NOW())
+        "#,
+    )
+    .bind(code)
+    .bind(user_id)
+    .fetch_optional(conn)
+    .await?;
+    Ok(tombstone.is_some())
+}
+
+async fn code_retired_sqlite(
+    conn: &mut SqliteConnection,
+    code: &str,
+    user_id: i32,
+) -> Result<bool, sqlx::Error> {
+    let tombstone = sqlx::query_as::<_, (String,)>(
+        r#"
+        SELECT short_code FROM code_tombstones
+        WHERE short_code = ?1
+            AND user_id IS NOT ?2
+            AND (expires_at IS NULL OR datetime(expires_at) > datetime('now'))
+        "#,
+    )
+    .bind(code)
+    .bind(user_id)
+    .fetch_optional(conn)
+    .await?;
+    Ok(tombstone.is_some())
+}
+
+/// Inserts the link under `code`, or returns `None` if the code is in use.
+async fn reserve_link_pg(
+    conn: &mut PgConnection,
+    user_id: i32,
+    new_link: &NewLink,
+    code: &str,
+) -> Result<Option<Link>, sqlx::Error> {
+    let link = sqlx::query_as::<_, Link>(
+        r#"
+        INSERT INTO links (original_url, short_code, user_id, created_at, clicks, title, description, notes, preview,
+            og_title, og_description, og_image, embeddable, public_stats)
+        VALUES ($1, $2, $3, COALESCE($4, NOW()), $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+        ON CONFLICT (short_code) DO NOTHING
+        RETURNING *
+        "#,
+    )
+    .bind(&new_link.url)
+    .bind(code)
+    .bind(user_id)
+    .bind(new_link.created_at)
+    .bind(new_link.clicks)
+    .bind(&new_link.title)
+    .bind(&new_link.description)
+    .bind(&new_link.notes)
+    .bind(new_link.preview)
+    .bind(&new_link.og_title)
+    .bind(&new_link.og_description)
+    .bind(&new_link.og_image)
+    .bind(new_link.embeddable)
+    .bind(new_link.public_stats)
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(link) = link else {
+        return Ok(None);
+    };
+
+    let reserved = sqlx::query(
+        "INSERT INTO link_codes (short_code, link_id) VALUES ($1, $2) ON CONFLICT (short_code) DO NOTHING",
+    )
+    .bind(code)
+    .bind(link.id)
+    .execute(&mut *conn)
+    .await?
+    .rows_affected()
+        == 1;
+    if !reserved {
+        // Another link has the code as an alias
+        sqlx::query("DELETE FROM links WHERE id = $1")
+            .bind(link.id)
+            .execute(&mut *conn)
             .await?;
+        return Ok(None);
+    }
+    Ok(Some(link))
+}
 
-            if let Some(ref source) = payload.source {
-                sqlx::query("INSERT INTO clicks (link_id, source) VALUES (?1, ?2)")
-                    .bind(link.id)
-                    .bind(source)
-                    .execute(&mut *tx)
-                    .await?;
+/// Inserts `new_link`. Its short code is reserved in the same statements,
+/// so a code taken since it was checked is reported as taken, or replaced
+/// with a fresh one if it was generated.
+async fn insert_link_pg(
+    conn: &mut PgConnection,
+    codes: &CodeGenerator,
+    user_id: i32,
+    new_link: &NewLink,
+) -> Result<Link, AppError> {
+    let mut code = new_link.short_code.clone();
+    let mut attempts = usize::from(!code.is_empty());
+    let mut link = loop {
+        if !code.is_empty() {
+            if let Some(link) = reserve_link_pg(conn, user_id, new_link, &code).await? {
+                break link;
+            }
+            if !new_link.generated {
+                return Err(AppError::InvalidInput(
+                    "Custom code already taken".to_string(),
+                ));
             }
-
-            tx.commit().await?;
-            link
         }
+        code = loop {
+            if attempts == MAX_GENERATE_ATTEMPTS {
+                return Err(no_free_code());
+            }
+            attempts += 1;
+            let candidate = codes.candidate_pg(conn).await?;
+            if validate_custom_code(&candidate).is_ok()
+                && !code_retired_pg(conn, &candidate, user_id).await?
+            {
+                break candidate;
+            }
+        };
     };
 
-    Ok(HttpResponse::Created().json(result))
+    if let Some(ref source) = new_link.source {
+        sqlx::query("INSERT INTO clicks (link_id, source, short_code) VALUES ($1, $2, $3)")
+            .bind(link.id)
+            .bind(source)
+            .bind(&link.short_code)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    set_link_tags_pg(conn, user_id, link.id, &new_link.tags).await?;
+    link.tags = new_link.tags.clone();
+
+    Ok(link)
 }
 
-fn validate_custom_code(code: &str) -> Result<(), AppError> {
-    if !VALID_CODE_REGEX.is_match(code) {
-        return Err(AppError::InvalidInput(
-            "Custom code must be 1-32 characters long and contain only letters, numbers, underscores, and hyphens".to_string()
-        ));
+/// Inserts the link under `code`, or returns `None` if the code is in use.
+async fn reserve_link_sqlite(
+    conn: &mut SqliteConnection,
+    user_id: i32,
+    new_link: &NewLink,
+    code: &str,
+) -> Result<Option<Link>, sqlx::Error> {
+    let link = sqlx::query_as::<_, Link>(
+        r#"
+        INSERT INTO links (original_url, short_code, user_id, created_at, clicks, title, description, notes, preview,
+            og_title, og_description, og_image, embeddable, public_stats)
+        VALUES (?1, ?2, ?3, COALESCE(?4, CURRENT_TIMESTAMP), ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+        ON CONFLICT (short_code) DO NOTHING
+        RETURNING *
+        "#,
+    )
+    .bind(&new_link.url)
+    .bind(code)
+    .bind(user_id)
+    .bind(new_link.created_at.as_ref().map(sqlite_timestamp))
+    .bind(new_link.clicks)
+    .bind(&new_link.title)
+    .bind(&new_link.description)
+    .bind(&new_link.notes)
+    .bind(new_link.preview)
+    .bind(&new_link.og_title)
+    .bind(&new_link.og_description)
+    .bind(&new_link.og_image)
+    .bind(new_link.embeddable)
+    .bind(new_link.public_stats)
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(link) = link else {
+        return Ok(None);
+    };
+
+    let reserved = sqlx::query(
+        "INSERT INTO link_codes (short_code, link_id) VALUES (?1, ?2) ON CONFLICT (short_code) DO NOTHING",
+    )
+    .bind(code)
+    .bind(link.id)
+    .execute(&mut *conn)
+    .await?
+    .rows_affected()
+        == 1;
+    if !reserved {
+        // Another link has the code as an alias
+        sqlx::query("DELETE FROM links WHERE id = ?1")
+            .bind(link.id)
+            .execute(&mut *conn)
+            .await?;
+        return Ok(None);
     }
+    Ok(Some(link))
+}
 
-    // Add reserved words check
-    let reserved_words = ["api", "health", "admin", "static", "assets"];
-    if reserved_words.contains(&code.to_lowercase().as_str()) {
-        return Err(AppError::InvalidInput(
-            "This code is reserved and cannot be used".to_string(),
-        ));
+/// Inserts `new_link`. Its short code is reserved in the same statements,
+/// so a code taken since it was checked is reported as taken, or replaced
+/// with a fresh one if it was generated.
+async fn insert_link_sqlite(
+    conn: &mut SqliteConnection,
+    codes: &CodeGenerator,
+    user_id: i32,
+    new_link: &NewLink,
+) -> Result<Link, AppError> {
+    let mut code = new_link.short_code.clone();
+    let mut attempts = usize::from(!code.is_empty());
+    let mut link = loop {
+        if !code.is_empty() {
+            if let Some(link) = reserve_link_sqlite(conn, user_id, new_link, &code).await? {
+                break link;
+            }
+            if !new_link.generated {
+                return Err(AppError::InvalidInput(
+                    "Custom code already taken".to_string(),
+                ));
+            }
+        }
+        code = loop {
+            if attempts == MAX_GENERATE_ATTEMPTS {
+                return Err(no_free_code());
+            }
+            attempts += 1;
+            let candidate = codes.candidate_sqlite(conn).await?;
+            if validate_custom_code(&candidate).is_ok()
+                && !code_retired_sqlite(conn, &candidate, user_id).await?
+            {
+                break candidate;
+            }
+        };
+    };
+
+    if let Some(ref source) = new_link.source {
+        sqlx::query("INSERT INTO clicks (link_id, source, short_code) VALUES (?1, ?2, ?3)")
+            .bind(link.id)
+            .bind(source)
+            .bind(&link.short_code)
+            .execute(&mut *conn)
+            .await?;
     }
 
-    Ok(())
+    set_link_tags_sqlite(conn, user_id, link.id, &new_link.tags).await?;
+    link.tags = new_link.tags.clone();
+
+    Ok(link)
 }
 
-fn validate_url(url: &str) -> Result<(), AppError> {
-    if url.is_empty() {
-        return Err(AppError::InvalidInput("URL cannot be empty".to_string()));
-    }
-    if !url.starts_with("http://") && !url.starts_with("https://") {
-        return Err(AppError::InvalidInput(
-            "URL must start with http:// or https://".to_string(),
-        ));
+/// Replaces a link's tags with `tags`, creating any the user doesn't have yet.
+async fn set_link_tags_pg(
+    conn: &mut PgConnection,
+    user_id: i32,
+    link_id: i32,
+    tags: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM link_tags WHERE link_id = $1")
+        .bind(link_id)
+        .execute(&mut *conn)
+        .await?;
+
+    for name in tags {
+        let (tag_id,) = sqlx::query_as::<_, (i32,)>(
+            r#"
+            INSERT INTO tags (user_id, name) VALUES ($1, $2)
+            ON CONFLICT (user_id, name) DO UPDATE SET name = EXCLUDED.name
+            RETURNING id
+            "#,
+        )
+        .bind(user_id)
+        .bind(name)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        sqlx::query("INSERT INTO link_tags (link_id, tag_id) VALUES ($1, $2)")
+            .bind(link_id)
+            .bind(tag_id)
+            .execute(&mut *conn)
+            .await?;
     }
+
     Ok(())
 }
 
-pub async fn redirect_to_url(
-    state: web::Data<AppState>,
-    path: web::Path<String>,
-    req: HttpRequest,
-) -> Result<impl Responder, AppError> {
-    let short_code = path.into_inner();
+/// Replaces a link's tags with `tags`, creating any the user doesn't have yet.
+async fn set_link_tags_sqlite(
+    conn: &mut SqliteConnection,
+    user_id: i32,
+    link_id: i32,
+    tags: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM link_tags WHERE link_id = ?1")
+        .bind(link_id)
+        .execute(&mut *conn)
+        .await?;
 
-    // Extract query source if present
-    let query_source = req
-        .uri()
-        .query()
-        .and_then(|q| web::Query::<std::collections::HashMap<String, String>>::from_query(q).ok())
-        .and_then(|params| params.get("source").cloned());
+    for name in tags {
+        let (tag_id,) = sqlx::query_as::<_, (i32,)>(
+            r#"
+            INSERT INTO tags (user_id, name) VALUES (?1, ?2)
+            ON CONFLICT (user_id, name) DO UPDATE SET name = excluded.name
+            RETURNING id
+            "#,
+        )
+        .bind(user_id)
+        .bind(name)
+        .fetch_one(&mut *conn)
+        .await?;
 
-    let link = match &state.db {
+        sqlx::query("INSERT INTO link_tags (link_id, tag_id) VALUES (?1, ?2)")
+            .bind(link_id)
+            .bind(tag_id)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Fills in the `tags` of each link with a single query.
+async fn attach_tags(db: &DatabasePool, mut links: Vec<&mut Link>) -> Result<(), AppError> {
+    if links.is_empty() {
+        return Ok(());
+    }
+    let ids: Vec<i32> = links.iter().map(|link| link.id).collect();
+
+    let rows = match db {
         DatabasePool::Postgres(pool) => {
-            let mut tx = pool.begin().await?;
-            let link = sqlx::query_as::<_, Link>(
-                "UPDATE links SET clicks = clicks + 1 WHERE short_code = $1 RETURNING *",
+            sqlx::query_as::<_, (i32, String)>(
+                r#"
+                SELECT link_tags.link_id, tags.name
+                FROM link_tags
+                JOIN tags ON tags.id = link_tags.tag_id
+                WHERE link_tags.link_id = ANY($1)
+                ORDER BY tags.name
+                "#,
             )
-            .bind(&short_code)
-            .fetch_optional(&mut *tx)
-            .await?;
-            tx.commit().await?;
-            link
+            .bind(&ids)
+            .fetch_all(pool)
+            .await?
         }
         DatabasePool::Sqlite(pool) => {
-            let mut tx = pool.begin().await?;
-            let link = sqlx::query_as::<_, Link>(
-                "UPDATE links SET clicks = clicks + 1 WHERE short_code = ?1 RETURNING *",
+            sqlx::query_as::<_, (i32, String)>(
+                r#"
+                SELECT link_tags.link_id, tags.name
+                FROM link_tags
+                JOIN tags ON tags.id = link_tags.tag_id
+                WHERE link_tags.link_id IN (SELECT value FROM json_each(?1))
+                ORDER BY tags.name
+                "#,
             )
-            .bind(&short_code)
-            .fetch_optional(&mut *tx)
-            .await?;
-            tx.commit().await?;
-            link
+            .bind(serde_json::to_string(&ids).unwrap_or_default())
+            .fetch_all(pool)
+            .await?
         }
     };
 
-    match link {
-        Some(link) => {
-            // Handle click recording based on database type
-            match &state.db {
-                DatabasePool::Postgres(pool) => {
-                    let mut tx = pool.begin().await?;
-                    let user_agent = req
-                        .headers()
-                        .get("user-agent")
-                        .and_then(|h| h.to_str().ok())
-                        .unwrap_or("unknown")
-                        .to_string();
-
-                    sqlx::query(
-                        "INSERT INTO clicks (link_id, source, query_source) VALUES ($1, $2, $3)",
-                    )
-                    .bind(link.id)
-                    .bind(user_agent)
-                    .bind(query_source)
-                    .execute(&mut *tx)
-                    .await?;
+    let mut by_link: HashMap<i32, Vec<String>> = HashMap::new();
+    for (link_id, name) in rows {
+        by_link.entry(link_id).or_default().push(name);
+    }
+    for link in links.iter_mut() {
+        link.tags = by_link.remove(&link.id).unwrap_or_default();
+    }
+    Ok(())
+}
+
+const MAX_BULK_LINKS: usize = 1000;
+
+pub async fn bulk_create_links(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    req: HttpRequest,
+    query: web::Query<BulkQuery>,
+    body: web::Bytes,
+) -> Result<impl Responder, AppError> {
+    let entries = parse_bulk_entries(&req, &body)?;
+    if entries.is_empty() {
+        return Err(AppError::InvalidInput("No links provided".to_string()));
+    }
+    if entries.len() > MAX_BULK_LINKS {
+        return Err(AppError::InvalidInput(format!(
+            "A bulk request can contain at most {} links",
+            MAX_BULK_LINKS
+        )));
+    }
+
+    let mode = query.mode;
+    let mut results = Vec::with_capacity(entries.len());
+    let mut prepared = Vec::new();
+    let mut claimed = HashSet::new();
+    let rules = DomainRules::load(&state.db, Some(user.user_id)).await?;
+    let resolved = state
+        .destinations
+        .resolve_hosts(
+            entries
+                .iter()
+                .filter_map(|entry| entry.as_ref().ok())
+                .map(|payload| payload.url.as_str()),
+        )
+        .await;
 
+    for (index, entry) in entries.into_iter().enumerate() {
+        let outcome = match entry {
+            Ok(payload) => {
+                let checked = state
+                    .destinations
+                    .check_with_rules(&rules, &resolved, user.user_id, &payload.url)
+                    .await;
+                let new_link = match checked {
+                    Ok(url) => prepare_link(&state, user.user_id, url, &payload).await,
+                    Err(err) => Err(err),
+                };
+                new_link.and_then(|new_link| {
+                    // Generated codes are made unique when they're inserted
+                    if new_link.generated || claimed.insert(new_link.short_code.clone()) {
+                        Ok(new_link)
+                    } else {
+                        Err(AppError::InvalidInput(
+                            "Custom code appears more than once in this batch".to_string(),
+                        ))
+                    }
+                })
+            }
+            Err(message) => Err(AppError::InvalidInput(message)),
+        };
+        match outcome {
+            Ok(new_link) => prepared.push((index, new_link)),
+            Err(err) => results.push(BulkLinkResult::failed(index, error_message(&err))),
+        }
+    }
+
+    if mode == BulkMode::Atomic && !results.is_empty() {
+        results.extend(
+            prepared
+                .iter()
+                .map(|(index, _)| BulkLinkResult::rolled_back(*index)),
+        );
+        return Ok(bulk_response(mode, results));
+    }
+
+    let inserted = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            let mut outcomes = Vec::with_capacity(prepared.len());
+            if mode == BulkMode::Atomic {
+                let mut tx = pool.begin().await?;
+                for (index, new_link) in &prepared {
+                    let outcome =
+                        insert_link_pg(&mut tx, &state.codes, user.user_id, new_link).await;
+                    let failed = outcome.is_err();
+                    outcomes.push((*index, outcome));
+                    if failed {
+                        break;
+                    }
+                }
+                if outcomes.iter().all(|(_, outcome)| outcome.is_ok()) {
                     tx.commit().await?;
+                } else {
+                    tx.rollback().await?;
                 }
-                DatabasePool::Sqlite(pool) => {
+            } else {
+                for (index, new_link) in &prepared {
                     let mut tx = pool.begin().await?;
-                    let user_agent = req
-                        .headers()
-                        .get("user-agent")
-                        .and_then(|h| h.to_str().ok())
-                        .unwrap_or("unknown")
-                        .to_string();
-
-                    sqlx::query(
-                        "INSERT INTO clicks (link_id, source, query_source) VALUES (?1, ?2, ?3)",
-                    )
-                    .bind(link.id)
-                    .bind(user_agent)
-                    .bind(query_source)
-                    .execute(&mut *tx)
-                    .await?;
-
+                    let outcome =
+                        insert_link_pg(&mut tx, &state.codes, user.user_id, new_link).await;
+                    if outcome.is_ok() {
+                        tx.commit().await?;
+                    }
+                    outcomes.push((*index, outcome));
+                }
+            }
+            outcomes
+        }
+        DatabasePool::Sqlite(pool) => {
+            let mut outcomes = Vec::with_capacity(prepared.len());
+            if mode == BulkMode::Atomic {
+                let mut tx = pool.begin().await?;
+                for (index, new_link) in &prepared {
+                    let outcome =
+                        insert_link_sqlite(&mut tx, &state.codes, user.user_id, new_link).await;
+                    let failed = outcome.is_err();
+                    outcomes.push((*index, outcome));
+                    if failed {
+                        break;
+                    }
+                }
+                if outcomes.iter().all(|(_, outcome)| outcome.is_ok()) {
                     tx.commit().await?;
+                } else {
+                    tx.rollback().await?;
                 }
-            };
-
-            Ok(HttpResponse::TemporaryRedirect()
-                .append_header(("Location", link.original_url))
-                .finish())
+            } else {
+                for (index, new_link) in &prepared {
+                    let mut tx = pool.begin().await?;
+                    let outcome =
+                        insert_link_sqlite(&mut tx, &state.codes, user.user_id, new_link).await;
+                    if outcome.is_ok() {
+                        tx.commit().await?;
+                    }
+                    outcomes.push((*index, outcome));
+                }
+            }
+            outcomes
         }
-        None => Err(AppError::NotFound),
+    };
+
+    let rolled_back = mode == BulkMode::Atomic && inserted.iter().any(|(_, o)| o.is_err());
+    let attempted: HashSet<usize> = inserted.iter().map(|(index, _)| *index).collect();
+    for (index, outcome) in inserted {
+        results.push(match outcome {
+            Err(err) => BulkLinkResult::failed(index, error_message(&err)),
+            Ok(_) if rolled_back => BulkLinkResult::rolled_back(index),
+            Ok(link) => {
+                if link.title.is_none() {
+                    state.metadata.enqueue(link.id, &link.original_url);
+                }
+                BulkLinkResult::created(index, link)
+            }
+        });
     }
+    // Rows after the failing one in an atomic batch were never attempted
+    results.extend(
+        prepared
+            .iter()
+            .filter(|(index, _)| !attempted.contains(index))
+            .map(|(index, _)| BulkLinkResult::rolled_back(*index)),
+    );
+
+    Ok(bulk_response(mode, results))
 }
 
-pub async fn get_all_links(
+/// Reads a bulk body as CSV (with a `url,custom_code,source` header row) or as
+/// a JSON array of `CreateLink` objects. Rows that fail to parse are kept as
+/// errors so they can be reported alongside the rest of the batch.
+fn parse_bulk_entries(
+    req: &HttpRequest,
+    body: &[u8],
+) -> Result<Vec<Result<CreateLink, String>>, AppError> {
+    let content_type = req
+        .headers()
+        .get("content-type")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("");
+
+    if content_type.starts_with("text/csv") {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(body);
+        Ok(reader
+            .deserialize::<CreateLink>()
+            .map(|row| row.map_err(|e| format!("Invalid CSV row: {}", e)))
+            .collect())
+    } else {
+        let values: Vec<serde_json::Value> = serde_json::from_slice(body).map_err(|e| {
+            AppError::InvalidInput(format!("Expected a JSON array of links: {}", e))
+        })?;
+        Ok(values
+            .into_iter()
+            .map(|value| {
+                serde_json::from_value::<CreateLink>(value)
+                    .map_err(|e| format!("Invalid link: {}", e))
+            })
+            .collect())
+    }
+}
+
+fn bulk_response(mode: BulkMode, mut results: Vec<BulkLinkResult>) -> HttpResponse {
+    results.sort_by_key(|r| r.index);
+    let created = results
+        .iter()
+        .filter(|r| r.status == BulkStatus::Created)
+        .count();
+    let failed = results
+        .iter()
+        .filter(|r| r.status == BulkStatus::Failed)
+        .count();
+    let body = BulkCreateResponse {
+        mode,
+        created,
+        failed,
+        results,
+    };
+
+    if failed == 0 {
+        HttpResponse::Created().json(body)
+    } else if mode == BulkMode::Atomic {
+        HttpResponse::BadRequest().json(body)
+    } else {
+        HttpResponse::Ok().json(body)
+    }
+}
+
+pub async fn export_links(
     state: web::Data<AppState>,
     user: AuthenticatedUser,
+    query: web::Query<ExportQuery>,
 ) -> Result<impl Responder, AppError> {
     let links = match &state.db {
         DatabasePool::Postgres(pool) => {
-            sqlx::query_as::<_, Link>(
-                "SELECT * FROM links WHERE user_id = $1 ORDER BY created_at DESC",
+            sqlx::query_as::<_, ExportedLink>(
+                r#"
+                SELECT short_code, original_url, created_at, clicks, title, description, notes
+                FROM links WHERE user_id = $1 AND deleted_at IS NULL ORDER BY id
+                "#,
             )
             .bind(user.user_id)
             .fetch_all(pool)
             .await?
         }
         DatabasePool::Sqlite(pool) => {
-            sqlx::query_as::<_, Link>(
-                "SELECT * FROM links WHERE user_id = ?1 ORDER BY created_at DESC",
+            sqlx::query_as::<_, ExportedLink>(
+                r#"
+                SELECT short_code, original_url, created_at, clicks, title, description, notes
+                FROM links WHERE user_id = ?1 AND deleted_at IS NULL ORDER BY id
+                "#,
             )
             .bind(user.user_id)
             .fetch_all(pool)
@@ -259,562 +897,3788 @@ pub async fn get_all_links(
         }
     };
 
-    Ok(HttpResponse::Ok().json(links))
-}
+    match query.format {
+        ExportFormat::Json => Ok(HttpResponse::Ok()
+            .insert_header((
+                "Content-Disposition",
+                "attachment; filename=\"simplelink-export.json\"",
+            ))
+            .json(LinkExport {
+                version: importers::EXPORT_VERSION,
+                exported_at: chrono::Utc::now(),
+                links,
+            })),
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for link in &links {
+                writer
+                    .serialize(link)
+                    .map_err(|e| AppError::InvalidInput(e.to_string()))?;
+            }
+            let body = writer
+                .into_inner()
+                .map_err(|e| AppError::InvalidInput(e.to_string()))?;
+            Ok(HttpResponse::Ok()
+                .content_type("text/csv")
+                .insert_header((
+                    "Content-Disposition",
+                    "attachment; filename=\"simplelink-export.csv\"",
+                ))
+                .body(body))
+        }
+    }
+}
+
+const MAX_IMPORT_LINKS: usize = 10_000;
+
+pub async fn import_links(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    req: HttpRequest,
+    query: web::Query<ImportQuery>,
+    body: web::Bytes,
+) -> Result<impl Responder, AppError> {
+    let format = query.format.unwrap_or_else(|| {
+        let content_type = req
+            .headers()
+            .get("content-type")
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or("");
+        if content_type.starts_with("text/csv") {
+            ImportFormat::Csv
+        } else if content_type.starts_with("text/html") {
+            ImportFormat::NetscapeHtml
+        } else {
+            ImportFormat::Json
+        }
+    });
+    let records = importers::parse(format, &body)?;
+
+    let response = run_import(&state, user.user_id, records, &query).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// What an import will do with one record once it has been checked.
+enum ImportAction {
+    Create { index: usize, new_link: NewLink },
+    Overwrite { link_id: i32, new_link: NewLink },
+}
+
+/// Checks every record, resolves short code conflicts and, unless this is a
+/// dry run, applies the result in a single transaction.
+async fn run_import(
+    state: &AppState,
+    user_id: i32,
+    records: Vec<Result<ExportedLink, String>>,
+    options: &ImportQuery,
+) -> Result<ImportResponse, AppError> {
+    if records.len() > MAX_IMPORT_LINKS {
+        return Err(AppError::InvalidInput(format!(
+            "An import can contain at most {} links",
+            MAX_IMPORT_LINKS
+        )));
+    }
+
+    let mut results = Vec::with_capacity(records.len());
+    let mut actions = Vec::new();
+    // Codes seen in the file, and codes this import is going to create
+    let mut seen = HashSet::new();
+    let mut claimed = HashSet::new();
+    let rules = DomainRules::load(&state.db, Some(user_id)).await?;
+    let resolved = state
+        .destinations
+        .resolve_hosts(
+            records
+                .iter()
+                .filter_map(|record| record.as_ref().ok())
+                .map(|record| record.original_url.as_str()),
+        )
+        .await;
+
+    // Look up every code in the file up front rather than once per record
+    let codes: Vec<String> = records
+        .iter()
+        .filter_map(|record| record.as_ref().ok())
+        .filter(|record| !record.short_code.is_empty())
+        .map(|record| record.short_code.clone())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    let owners = code_owners(&state.db, &codes).await?;
+    let retired_codes = retired_codes(&state.db, &codes, user_id).await?;
+
+    for (index, record) in records.into_iter().enumerate() {
+        let record = match record {
+            Ok(record) => record,
+            Err(message) => {
+                results.push(ImportResult::failed(index, None, message));
+                continue;
+            }
+        };
+        // Records without a code, such as plain bookmarks, get a generated
+        // one as they're inserted, so that a dry run doesn't use any up
+        let generated = record.short_code.is_empty();
+        let code = record.short_code.clone();
+        let checked = state
+            .destinations
+            .check_with_rules(&rules, &resolved, user_id, &record.original_url)
+            .await
+            .and_then(|url| {
+                if !generated {
+                    validate_custom_code(&code)?;
+                }
+                Ok((
+                    url,
+                    normalize_text("Title", record.title.as_deref(), MAX_TITLE_LEN)?,
+                    normalize_text(
+                        "Description",
+                        record.description.as_deref(),
+                        MAX_DESCRIPTION_LEN,
+                    )?,
+                    normalize_text("Notes", record.notes.as_deref(), MAX_NOTES_LEN)?,
+                ))
+            });
+        let (url, title, description, notes) = match checked {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                let code = Some(code).filter(|code| !code.is_empty());
+                results.push(ImportResult::failed(index, code, error_message(&err)));
+                continue;
+            }
+        };
+
+        let new_link = NewLink {
+            url,
+            short_code: code.clone(),
+            generated,
+            source: None,
+            created_at: record.created_at,
+            clicks: record.clicks.max(0),
+            title,
+            description,
+            notes,
+            tags: Vec::new(),
+            preview: false,
+            og_title: None,
+            og_description: None,
+            og_image: None,
+            embeddable: false,
+            public_stats: false,
+        };
+        if generated {
+            results.push(ImportResult {
+                short_code: None,
+                ..ImportResult::new(index, code, ImportStatus::Created)
+            });
+            actions.push(ImportAction::Create { index, new_link });
+            continue;
+        }
+        if !seen.insert(code.clone()) {
+            results.push(ImportResult::failed(
+                index,
+                Some(code),
+                "Short code appears more than once in this import".to_string(),
+            ));
+            continue;
+        }
+
+        // A code can also be held by an earlier record that was renamed onto it
+        let existing = owners.get(&code).copied();
+        let retired = existing.is_none() && retired_codes.contains(&code);
+        if existing.is_none() && !retired && !claimed.contains(&code) {
+            claimed.insert(code.clone());
+            results.push(ImportResult::new(index, code, ImportStatus::Created));
+            actions.push(ImportAction::Create { index, new_link });
+            continue;
+        }
+
+        match options.on_conflict {
+            ConflictStrategy::Skip => {
+                results.push(ImportResult::new(index, code, ImportStatus::Skipped));
+            }
+            ConflictStrategy::Overwrite => match existing {
+                Some((link_id, Some(owner), false)) if owner == user_id => {
+                    results.push(ImportResult::new(index, code, ImportStatus::Overwritten));
+                    actions.push(ImportAction::Overwrite { link_id, new_link });
+                }
+                Some((_, Some(owner), true)) if owner == user_id => {
+                    results.push(ImportResult::failed(
+                        index,
+                        Some(code),
+                        "The link with this code is in the trash, restore it first".to_string(),
+                    ));
+                }
+                None if retired => {
+                    results.push(ImportResult::failed(
+                        index,
+                        Some(code),
+                        CODE_RETIRED_MESSAGE.to_string(),
+                    ));
+                }
+                _ => {
+                    results.push(ImportResult::failed(
+                        index,
+                        Some(code),
+                        "Short code belongs to another link".to_string(),
+                    ));
+                }
+            },
+            ConflictStrategy::Rename => {
+                let renamed = free_code_like(state, user_id, &code, &claimed).await?;
+                if let Some(ref renamed) = renamed {
+                    claimed.insert(renamed.clone());
+                }
+                let mut result = ImportResult::new(index, code, ImportStatus::Renamed);
+                result.new_code = renamed.clone();
+                results.push(result);
+                actions.push(ImportAction::Create {
+                    index,
+                    new_link: NewLink {
+                        short_code: renamed.unwrap_or_default(),
+                        generated: true,
+                        ..new_link
+                    },
+                });
+            }
+        }
+    }
+
+    // Links keeping their own code go in first, so that no code drawn for
+    // another link can take it
+    actions.sort_by_key(
+        |action| matches!(action, ImportAction::Create { new_link, .. } if new_link.generated),
+    );
+    // Generated codes taken while the import ran, and the codes used instead
+    let mut replaced = Vec::new();
+    if !options.dry_run && !actions.is_empty() {
+        match &state.db {
+            DatabasePool::Postgres(pool) => {
+                let mut tx = pool.begin().await?;
+                for action in &actions {
+                    match action {
+                        ImportAction::Create { index, new_link } => {
+                            let link =
+                                insert_link_pg(&mut tx, &state.codes, user_id, new_link).await?;
+                            if link.short_code != new_link.short_code {
+                                replaced.push((*index, link.short_code));
+                            }
+                        }
+                        ImportAction::Overwrite { link_id, new_link } => {
+                            let (old_url, code) = sqlx::query_as::<_, (String, String)>(
+                                "SELECT original_url, short_code FROM links WHERE id = $1",
+                            )
+                            .bind(link_id)
+                            .fetch_one(&mut *tx)
+                            .await?;
+                            sqlx::query(
+                                r#"
+                                UPDATE links
+                                SET original_url = $1, created_at = COALESCE($2, created_at),
+                                    title = COALESCE($3, title),
+                                    description = COALESCE($4, description),
+                                    notes = COALESCE($5, notes),
+                                    version = version + 1
+                                WHERE id = $6 AND user_id = $7
+                                "#,
+                            )
+                            .bind(&new_link.url)
+                            .bind(new_link.created_at)
+                            .bind(&new_link.title)
+                            .bind(&new_link.description)
+                            .bind(&new_link.notes)
+                            .bind(link_id)
+                            .bind(user_id)
+                            .execute(&mut *tx)
+                            .await?;
+                            if old_url != new_link.url {
+                                sqlx::query(
+                                    r#"
+                                    INSERT INTO link_revisions (link_id, user_id, old_url, new_url, old_code, new_code)
+                                    VALUES ($1, $2, $3, $4, $5, $5)
+                                    "#,
+                                )
+                                .bind(link_id)
+                                .bind(user_id)
+                                .bind(&old_url)
+                                .bind(&new_link.url)
+                                .bind(&code)
+                                .execute(&mut *tx)
+                                .await?;
+                            }
+                        }
+                    }
+                }
+                tx.commit().await?;
+            }
+            DatabasePool::Sqlite(pool) => {
+                let mut tx = pool.begin().await?;
+                for action in &actions {
+                    match action {
+                        ImportAction::Create { index, new_link } => {
+                            let link = insert_link_sqlite(&mut tx, &state.codes, user_id, new_link)
+                                .await?;
+                            if link.short_code != new_link.short_code {
+                                replaced.push((*index, link.short_code));
+                            }
+                        }
+                        ImportAction::Overwrite { link_id, new_link } => {
+                            let (old_url, code) = sqlx::query_as::<_, (String, String)>(
+                                "SELECT original_url, short_code FROM links WHERE id = ?1",
+                            )
+                            .bind(link_id)
+                            .fetch_one(&mut *tx)
+                            .await?;
+                            sqlx::query(
+                                r#"
+                                UPDATE links
+                                SET original_url = ?1, created_at = COALESCE(?2, created_at),
+                                    title = COALESCE(?3, title),
+                                    description = COALESCE(?4, description),
+                                    notes = COALESCE(?5, notes),
+                                    version = version + 1
+                                WHERE id = ?6 AND user_id = ?7
+                                "#,
+                            )
+                            .bind(&new_link.url)
+                            .bind(new_link.created_at.as_ref().map(sqlite_timestamp))
+                            .bind(&new_link.title)
+                            .bind(&new_link.description)
+                            .bind(&new_link.notes)
+                            .bind(link_id)
+                            .bind(user_id)
+                            .execute(&mut *tx)
+                            .await?;
+                            if old_url != new_link.url {
+                                sqlx::query(
+                                    r#"
+                                    INSERT INTO link_revisions (link_id, user_id, old_url, new_url, old_code, new_code)
+                                    VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+                                    "#,
+                                )
+                                .bind(link_id)
+                                .bind(user_id)
+                                .bind(&old_url)
+                                .bind(&new_link.url)
+                                .bind(&code)
+                                .execute(&mut *tx)
+                                .await?;
+                            }
+                        }
+                    }
+                }
+                tx.commit().await?;
+            }
+        }
+    }
+
+    for (index, code) in replaced {
+        if let Some(result) = results.iter_mut().find(|result| result.index == index) {
+            if result.status == ImportStatus::Renamed {
+                result.new_code = Some(code);
+            } else {
+                result.short_code = Some(code);
+            }
+        }
+    }
+
+    let count = |status: ImportStatus| results.iter().filter(|r| r.status == status).count();
+    Ok(ImportResponse {
+        dry_run: options.dry_run,
+        created: count(ImportStatus::Created),
+        overwritten: count(ImportStatus::Overwritten),
+        renamed: count(ImportStatus::Renamed),
+        skipped: count(ImportStatus::Skipped),
+        failed: count(ImportStatus::Failed),
+        results,
+    })
+}
+
+/// Returns the id and owner of the link using `code`, either as its short
+/// code or as an alias, if any. Both live in `link_codes`.
+async fn code_owner(db: &DatabasePool, code: &str) -> Result<Option<(i32, Option<i32>)>, AppError> {
+    let owner = match db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, (i32, Option<i32>)>(
+                r#"
+                SELECT links.id, links.user_id FROM link_codes
+                JOIN links ON links.id = link_codes.link_id
+                WHERE link_codes.short_code = $1
+                "#,
+            )
+            .bind(code)
+            .fetch_optional(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, (i32, Option<i32>)>(
+                r#"
+                SELECT links.id, links.user_id FROM link_codes
+                JOIN links ON links.id = link_codes.link_id
+                WHERE link_codes.short_code = ?1
+                "#,
+            )
+            .bind(code)
+            .fetch_optional(pool)
+            .await?
+        }
+    };
+    Ok(owner)
+}
+
+/// Like `code_owner`, for many codes at once, also telling whether the link is in the trash.
+async fn code_owners(
+    db: &DatabasePool,
+    codes: &[String],
+) -> Result<HashMap<String, (i32, Option<i32>, bool)>, AppError> {
+    if codes.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let rows = match db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, (String, i32, Option<i32>, bool)>(
+                r#"
+                SELECT link_codes.short_code, links.id, links.user_id, links.deleted_at IS NOT NULL
+                FROM link_codes
+                JOIN links ON links.id = link_codes.link_id
+                WHERE link_codes.short_code = ANY($1)
+                "#,
+            )
+            .bind(codes)
+            .fetch_all(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, (String, i32, Option<i32>, bool)>(
+                r#"
+                SELECT link_codes.short_code, links.id, links.user_id, links.deleted_at IS NOT NULL
+                FROM link_codes
+                JOIN links ON links.id = link_codes.link_id
+                WHERE link_codes.short_code IN (SELECT value FROM json_each(?1))
+                "#,
+            )
+            .bind(serde_json::to_string(codes).unwrap_or_default())
+            .fetch_all(pool)
+            .await?
+        }
+    };
+    Ok(rows
+        .into_iter()
+        .map(|(code, id, owner, trashed)| (code, (id, owner, trashed)))
+        .collect())
+}
+
+/// The codes among `codes` that `code_retired` would refuse to `user_id`.
+async fn retired_codes(
+    db: &DatabasePool,
+    codes: &[String],
+    user_id: i32,
+) -> Result<HashSet<String>, AppError> {
+    if codes.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let rows = match db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, (String,)>(
+                r#"
+                SELECT short_code FROM code_tombstones
+                WHERE short_code = ANY($1)
+                    AND user_id IS DISTINCT FROM $2
+                    AND (expires_at IS NULL OR expires_at > NOW())
+                "#,
+            )
+            .bind(codes)
+            .bind(user_id)
+            .fetch_all(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, (String,)>(
+                r#"
+                SELECT short_code FROM code_tombstones
+                WHERE short_code IN (SELECT value FROM json_each(?1))
+                    AND user_id IS NOT ?2
+                    AND (expires_at IS NULL OR datetime(expires_at) > datetime('now'))
+                "#,
+            )
+            .bind(serde_json::to_string(codes).unwrap_or_default())
+            .bind(user_id)
+            .fetch_all(pool)
+            .await?
+        }
+    };
+    Ok(rows.into_iter().map(|(code,)| code).collect())
+}
+
+/// Finds an unused code derived from `code` by appending `-2`, `-3`, ...
+/// or `None` if the name is too long to extend, in which case a code is
+/// generated as the link is inserted.
+async fn free_code_like(
+    state: &AppState,
+    user_id: i32,
+    code: &str,
+    claimed: &HashSet<String>,
+) -> Result<Option<String>, AppError> {
+    let db = &state.db;
+    for n in 2..100 {
+        let candidate = format!("{}-{}", code, n);
+        if candidate.len() > 32 {
+            break;
+        }
+        if !claimed.contains(&candidate)
+            && !code_taken(db, &candidate).await?
+            && !code_retired(db, &candidate, user_id).await?
+        {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+/// The message reported for a single failed row, without the variant prefix
+/// that `AppError`'s `Display` adds.
+fn error_message(err: &AppError) -> String {
+    match err {
+        AppError::InvalidInput(msg) | AppError::Auth(msg) => msg.clone(),
+        other => other.to_string(),
+    }
+}
+
+pub(crate) fn validate_custom_code(code: &str) -> Result<(), AppError> {
+    if !VALID_CODE_REGEX.is_match(code) {
+        return Err(AppError::InvalidInput(
+            "Custom code must be 1-32 characters long and contain only letters, numbers, underscores, and hyphens".to_string()
+        ));
+    }
+
+    // Add reserved words check
+    let reserved_words = ["api", "health", "admin", "static", "assets"];
+    if reserved_words.contains(&code.to_lowercase().as_str()) {
+        return Err(AppError::InvalidInput(
+            "This code is reserved and cannot be used".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+const MAX_NOTES_LEN: usize = 10_000;
+const MAX_OG_IMAGE_LEN: usize = 2048;
+
+/// Trims an Open Graph image URL and checks that it is an absolute `http` or
+/// `https` URL. An empty string is passed through so edits can clear it.
+fn normalize_og_image(value: Option<&str>) -> Result<Option<String>, AppError> {
+    let Some(value) = edited_text("Open Graph image", value, MAX_OG_IMAGE_LEN)? else {
+        return Ok(None);
+    };
+    if value.is_empty() {
+        return Ok(Some(value));
+    }
+    match reqwest::Url::parse(&value) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(Some(url.into())),
+        _ => Err(AppError::InvalidInput(
+            "Open Graph image must be an http or https URL".to_string(),
+        )),
+    }
+}
+
+/// Trims an optional text field, treating blank values as absent.
+fn normalize_text(
+    field: &str,
+    value: Option<&str>,
+    max_len: usize,
+) -> Result<Option<String>, AppError> {
+    Ok(edited_text(field, value, max_len)?.filter(|v| !v.is_empty()))
+}
+
+/// Like `normalize_text`, but keeps blank values as `Some("")` so that an
+/// edit can tell "clear this field" apart from "leave it alone".
+fn edited_text(
+    field: &str,
+    value: Option<&str>,
+    max_len: usize,
+) -> Result<Option<String>, AppError> {
+    let Some(value) = value.map(str::trim) else {
+        return Ok(None);
+    };
+    if value.chars().count() > max_len {
+        return Err(AppError::InvalidInput(format!(
+            "{} must be at most {} characters long",
+            field, max_len
+        )));
+    }
+    Ok(Some(value.to_string()))
+}
+
+const MAX_TAGS_PER_LINK: usize = 20;
+
+/// Trims and lowercases a tag name. Slashes separate folder levels, so
+/// ` Marketing / Q3 ` becomes `marketing/q3`.
+fn normalize_tag(name: &str) -> Result<String, AppError> {
+    let name = name
+        .split('/')
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join("/")
+        .to_lowercase();
+    if name.chars().count() > 64 || !VALID_TAG_REGEX.is_match(&name) {
+        return Err(AppError::InvalidInput(
+            "Tag names must be 1-64 characters long and contain only letters, numbers, spaces, dots, underscores, and hyphens, with slashes between folder levels".to_string()
+        ));
+    }
+    Ok(name)
+}
+
+fn normalize_tags(names: &[String]) -> Result<Vec<String>, AppError> {
+    let mut tags = Vec::new();
+    for name in names {
+        let tag = normalize_tag(name)?;
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    if tags.len() > MAX_TAGS_PER_LINK {
+        return Err(AppError::InvalidInput(format!(
+            "A link can have at most {} tags",
+            MAX_TAGS_PER_LINK
+        )));
+    }
+    tags.sort();
+    Ok(tags)
+}
+
+pub async fn redirect_to_url(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> Result<impl Responder, AppError> {
+    let short_code = path.into_inner();
+    // `/{code}+` asks for the preview page instead of the redirect
+    let (short_code, preview_requested) = match short_code.strip_suffix('+') {
+        Some(code) => (code.to_string(), true),
+        None => (short_code, false),
+    };
+
+    // Extract query source if present
+    let params = req
+        .uri()
+        .query()
+        .and_then(|q| web::Query::<std::collections::HashMap<String, String>>::from_query(q).ok())
+        .map(|params| params.into_inner())
+        .unwrap_or_default();
+    let query_source = params.get("source").cloned();
+
+    let Some(link) = find_link_by_code(&state.db, &short_code).await? else {
+        return Err(AppError::NotFound);
+    };
+
+    // Disabled links keep their code but neither redirect nor count clicks
+    if !link.enabled {
+        return Ok(state.disabled_page.render());
+    }
+
+    let user_agent = req
+        .headers()
+        .get("user-agent")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("unknown")
+        .to_string();
+
+    // Chat apps fetching the link to unfurl it aren't counted as clicks.
+    // They get the owner's Open Graph tags if there are any, or the
+    // destination to read its own tags from
+    if pages::is_unfurl_crawler(&user_agent) {
+        let has_og =
+            link.og_title.is_some() || link.og_description.is_some() || link.og_image.is_some();
+        if !has_og {
+            return Ok(HttpResponse::TemporaryRedirect()
+                .append_header(("Location", link.original_url))
+                .finish());
+        }
+        let url = short_url(&state, &req, &short_code);
+        return Ok(Unfurl {
+            url: &url,
+            destination: &link.original_url,
+            title: link
+                .og_title
+                .as_deref()
+                .or(link.title.as_deref())
+                .unwrap_or(&link.original_url),
+            description: link
+                .og_description
+                .as_deref()
+                .or(link.description.as_deref()),
+            image: link.og_image.as_deref(),
+        }
+        .render());
+    }
+
+    // Previews don't count as clicks, following the continue button does
+    if preview_requested || (link.preview && !params.contains_key("confirm")) {
+        let owner = display_name(&state.db, link.user_id).await?;
+        let continue_url = match req.uri().query() {
+            Some(query) => format!("/{}?{}&confirm=1", short_code, query),
+            None => format!("/{}?confirm=1", short_code),
+        };
+        return Ok(Preview {
+            short_code: &short_code,
+            destination: &link.original_url,
+            title: link.title.as_deref(),
+            owner: owner.as_deref(),
+            continue_url: &continue_url,
+        }
+        .render());
+    }
+
+    // Handle click recording based on database type
+    match &state.db {
+        DatabasePool::Postgres(pool) => {
+            let mut tx = pool.begin().await?;
+            sqlx::query("UPDATE links SET clicks = clicks + 1 WHERE id = $1")
+                .bind(link.id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query(
+                "INSERT INTO clicks (link_id, source, query_source, short_code) VALUES ($1, $2, $3, $4)",
+            )
+            .bind(link.id)
+            .bind(user_agent)
+            .bind(query_source)
+            .bind(&short_code)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+        }
+        DatabasePool::Sqlite(pool) => {
+            let mut tx = pool.begin().await?;
+            sqlx::query("UPDATE links SET clicks = clicks + 1 WHERE id = ?1")
+                .bind(link.id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query(
+                "INSERT INTO clicks (link_id, source, query_source, short_code) VALUES (?1, ?2, ?3, ?4)",
+            )
+            .bind(link.id)
+            .bind(user_agent)
+            .bind(query_source)
+            .bind(&short_code)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+        }
+    };
+
+    Ok(HttpResponse::TemporaryRedirect()
+        .append_header(("Location", link.original_url))
+        .finish())
+}
+
+/// Finds the link a short code or alias points at, leaving out trashed links.
+async fn find_link_by_code(db: &DatabasePool, code: &str) -> Result<Option<Link>, AppError> {
+    let link = match db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, Link>(
+                r#"
+                SELECT links.* FROM link_codes
+                JOIN links ON links.id = link_codes.link_id
+                WHERE link_codes.short_code = $1 AND links.deleted_at IS NULL
+                "#,
+            )
+            .bind(code)
+            .fetch_optional(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, Link>(
+                r#"
+                SELECT links.* FROM link_codes
+                JOIN links ON links.id = link_codes.link_id
+                WHERE link_codes.short_code = ?1 AND links.deleted_at IS NULL
+                "#,
+            )
+            .bind(code)
+            .fetch_optional(pool)
+            .await?
+        }
+    };
+    Ok(link)
+}
+
+/// The short URL for `code` on the first of `SHORT_LINK_HOSTS`, or on the
+/// host the request came in on if that isn't set.
+fn short_url(state: &AppState, req: &HttpRequest, code: &str) -> String {
+    match state.destinations.base_url() {
+        Some(base) => format!("{}/{}", base, code),
+        None => {
+            let info = req.connection_info();
+            format!("{}://{}/{}", info.scheme(), info.host(), code)
+        }
+    }
+}
+
+/// The name shown for a link's owner on its preview page.
+async fn display_name(db: &DatabasePool, user_id: Option<i32>) -> Result<Option<String>, AppError> {
+    let Some(user_id) = user_id else {
+        return Ok(None);
+    };
+    let name = match db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, (Option<String>,)>("SELECT display_name FROM users WHERE id = $1")
+                .bind(user_id)
+                .fetch_optional(pool)
+                .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, (Option<String>,)>("SELECT display_name FROM users WHERE id = ?1")
+                .bind(user_id)
+                .fetch_optional(pool)
+                .await?
+        }
+    };
+    Ok(name.and_then(|(name,)| name))
+}
+
+pub async fn get_all_links(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    query: web::Query<LinkListQuery>,
+) -> Result<impl Responder, AppError> {
+    let mut query = query.into_inner();
+    query.tag = query.tag.as_deref().map(normalize_tag).transpose()?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let cursor = match query.cursor.as_deref() {
+        Some(raw) => {
+            let cursor = ListCursor::decode(raw)?;
+            if cursor.sort != query.sort || cursor.order != query.order {
+                return Err(AppError::InvalidInput(
+                    "Cursor does not match the requested sort order".to_string(),
+                ));
+            }
+            Some(cursor)
+        }
+        None => None,
+    };
+
+    let is_sqlite = matches!(state.db, DatabasePool::Sqlite(_));
+    let (sql, params) =
+        build_link_list_sql(&query, user.user_id, cursor.as_ref(), limit, is_sqlite);
+
+    let mut links = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            let mut q = sqlx::query_as::<Postgres, Link>(&sql);
+            for param in params {
+                q = match param {
+                    SqlParam::Int(v) => q.bind(v),
+                    SqlParam::Text(v) => q.bind(v),
+                    SqlParam::Timestamp(v) => q.bind(v),
+                };
+            }
+            q.fetch_all(pool).await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            let mut q = sqlx::query_as::<Sqlite, Link>(&sql);
+            for param in params {
+                q = match param {
+                    SqlParam::Int(v) => q.bind(v),
+                    SqlParam::Text(v) => q.bind(v),
+                    SqlParam::Timestamp(v) => q.bind(sqlite_timestamp(&v)),
+                };
+            }
+            q.fetch_all(pool).await?
+        }
+    };
+
+    // We fetch one extra row to find out whether another page exists
+    let next_cursor = if links.len() as i64 > limit {
+        links.truncate(limit as usize);
+        links
+            .last()
+            .map(|last| ListCursor::after(last, query.sort, query.order).encode())
+    } else {
+        None
+    };
+    attach_tags(&state.db, links.iter_mut().collect()).await?;
+
+    Ok(HttpResponse::Ok().json(LinkPage { links, next_cursor }))
+}
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+/// A value bound into a dynamically built query. SQLite stores timestamps as
+/// text, so they are bound differently per backend.
+enum SqlParam {
+    Int(i64),
+    Text(String),
+    Timestamp(chrono::DateTime<chrono::Utc>),
+}
+
+/// Position of the last link on a page. Carries the sort key as well as the
+/// id so that ties on clicks or creation time page deterministically.
+#[derive(Serialize, Deserialize)]
+struct ListCursor {
+    sort: LinkSort,
+    order: SortOrder,
+    value: String,
+    id: i32,
+}
+
+impl ListCursor {
+    fn after(link: &Link, sort: LinkSort, order: SortOrder) -> Self {
+        let value = match sort {
+            LinkSort::CreatedAt => link.created_at.to_rfc3339(),
+            LinkSort::Clicks => link.clicks.to_string(),
+            LinkSort::ShortCode => link.short_code.clone(),
+        };
+        Self {
+            sort,
+            order,
+            value,
+            id: link.id,
+        }
+    }
+
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    fn decode(raw: &str) -> Result<Self, AppError> {
+        let invalid = || AppError::InvalidInput("Invalid cursor".to_string());
+        let bytes = URL_SAFE_NO_PAD.decode(raw).map_err(|_| invalid())?;
+        let cursor: Self = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+        cursor.param()?;
+        Ok(cursor)
+    }
+
+    fn param(&self) -> Result<SqlParam, AppError> {
+        let invalid = || AppError::InvalidInput("Invalid cursor".to_string());
+        Ok(match self.sort {
+            LinkSort::CreatedAt => SqlParam::Timestamp(
+                chrono::DateTime::parse_from_rfc3339(&self.value)
+                    .map_err(|_| invalid())?
+                    .with_timezone(&chrono::Utc),
+            ),
+            LinkSort::Clicks => SqlParam::Int(self.value.parse().map_err(|_| invalid())?),
+            LinkSort::ShortCode => SqlParam::Text(self.value.clone()),
+        })
+    }
+}
+
+fn sqlite_timestamp(ts: &chrono::DateTime<chrono::Utc>) -> String {
+    ts.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn build_link_list_sql(
+    query: &LinkListQuery,
+    user_id: i32,
+    cursor: Option<&ListCursor>,
+    limit: i64,
+    is_sqlite: bool,
+) -> (String, Vec<SqlParam>) {
+    let mut params = vec![SqlParam::Int(user_id as i64)];
+    let placeholder = |n: usize| {
+        if is_sqlite {
+            format!("?{}", n)
+        } else {
+            format!("${}", n)
+        }
+    };
+    // SQLite keeps CURRENT_TIMESTAMP text, normalise it so comparisons are reliable
+    let created_at = if is_sqlite {
+        "datetime(created_at)"
+    } else {
+        "created_at"
+    };
+
+    let mut sql = format!(
+        "SELECT * FROM links WHERE user_id = {} AND deleted_at IS NULL",
+        placeholder(1)
+    );
+
+    if let Some(q) = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        params.push(SqlParam::Text(format!(
+            "%{}%",
+            escape_like(&q.to_lowercase())
+        )));
+        let p = placeholder(params.len());
+        sql.push_str(&format!(
+            " AND (LOWER(short_code) LIKE {p} ESCAPE '\\' OR LOWER(original_url) LIKE {p} ESCAPE '\\' \
+             OR LOWER(title) LIKE {p} ESCAPE '\\' OR LOWER(description) LIKE {p} ESCAPE '\\' \
+             OR LOWER(notes) LIKE {p} ESCAPE '\\')"
+        ));
+    }
+    if let Some(after) = query.created_after {
+        params.push(SqlParam::Timestamp(after));
+        sql.push_str(&format!(
+            " AND {} >= {}",
+            created_at,
+            placeholder(params.len())
+        ));
+    }
+    if let Some(before) = query.created_before {
+        params.push(SqlParam::Timestamp(before));
+        sql.push_str(&format!(
+            " AND {} < {}",
+            created_at,
+            placeholder(params.len())
+        ));
+    }
+    if let Some(min) = query.min_clicks {
+        params.push(SqlParam::Int(min));
+        sql.push_str(&format!(" AND clicks >= {}", placeholder(params.len())));
+    }
+    if let Some(max) = query.max_clicks {
+        params.push(SqlParam::Int(max));
+        sql.push_str(&format!(" AND clicks <= {}", placeholder(params.len())));
+    }
+    if let Some(tag) = query.tag.as_deref() {
+        // A tag also matches the links in its sub-folders
+        params.push(SqlParam::Text(tag.to_string()));
+        let name_p = placeholder(params.len());
+        params.push(SqlParam::Text(format!("{}/%", escape_like(tag))));
+        let prefix_p = placeholder(params.len());
+        let user_p = placeholder(1);
+        sql.push_str(&format!(
+            " AND id IN (SELECT link_tags.link_id FROM link_tags JOIN tags ON tags.id = link_tags.tag_id \
+             WHERE tags.user_id = {user_p} AND (tags.name = {name_p} OR tags.name LIKE {prefix_p} ESCAPE '\\'))"
+        ));
+    }
+
+    let sort_column = match query.sort {
+        LinkSort::CreatedAt => created_at,
+        LinkSort::Clicks => "clicks",
+        LinkSort::ShortCode => "short_code",
+    };
+    let (direction, comparison) = match query.order {
+        SortOrder::Asc => ("ASC", ">"),
+        SortOrder::Desc => ("DESC", "<"),
+    };
+
+    if let Some(cursor) = cursor {
+        // Cursors are validated when decoded, so this cannot fail here
+        if let Ok(value) = cursor.param() {
+            params.push(value);
+            let value_p = placeholder(params.len());
+            params.push(SqlParam::Int(cursor.id as i64));
+            let id_p = placeholder(params.len());
+            sql.push_str(&format!(
+                " AND ({sort_column}, id) {comparison} ({value_p}, {id_p})"
+            ));
+        }
+    }
+
+    params.push(SqlParam::Int(limit + 1));
+    sql.push_str(&format!(
+        " ORDER BY {sort_column} {direction}, id {direction} LIMIT {}",
+        placeholder(params.len())
+    ));
+
+    (sql, params)
+}
+
+pub async fn search_links(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    query: web::Query<SearchQuery>,
+) -> Result<impl Responder, AppError> {
+    let terms = search_terms(&query.q);
+    if terms.is_empty() {
+        return Err(AppError::InvalidInput(
+            "Search query must contain at least one word".to_string(),
+        ));
+    }
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let mut results = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            // Every term is a prefix match and all of them must be present
+            let ts_query = terms
+                .iter()
+                .map(|t| format!("{}:*", t))
+                .collect::<Vec<_>>()
+                .join(" & ");
+            sqlx::query_as::<_, LinkSearchResult>(
+                r#"
+                SELECT links.*, ts_rank(search_vector, query)::float8 AS rank
+                FROM links, to_tsquery('simple', $2) query
+                WHERE user_id = $1 AND deleted_at IS NULL AND search_vector @@ query
+                ORDER BY rank DESC, id DESC
+                LIMIT $3 OFFSET $4
+                "#,
+            )
+            .bind(user.user_id)
+            .bind(ts_query)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            let fts_query = terms
+                .iter()
+                .map(|t| format!("\"{}\"*", t))
+                .collect::<Vec<_>>()
+                .join(" ");
+            // bm25 scores are lower-is-better, flip them to match Postgres
+            sqlx::query_as::<_, LinkSearchResult>(
+                r#"
+                SELECT links.*, -bm25(links_fts, 10.0, 4.0, 10.0, 2.0, 2.0) AS rank
+                FROM links_fts
+                JOIN links ON links.id = links_fts.rowid
+                WHERE links_fts MATCH ?2 AND links.user_id = ?1 AND links.deleted_at IS NULL
+                ORDER BY rank DESC, links.id DESC
+                LIMIT ?3 OFFSET ?4
+                "#,
+            )
+            .bind(user.user_id)
+            .bind(fts_query)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(pool)
+            .await?
+        }
+    };
+
+    attach_tags(&state.db, results.iter_mut().map(|r| &mut r.link).collect()).await?;
+
+    Ok(HttpResponse::Ok().json(results))
+}
+
+/// Splits a free-text query into lowercase alphanumeric words, which are safe
+/// to splice into tsquery and FTS5 match expressions.
+fn search_terms(q: &str) -> Vec<String> {
+    q.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .take(16)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+pub async fn health_check(state: web::Data<AppState>) -> impl Responder {
+    let is_healthy = match &state.db {
+        DatabasePool::Postgres(pool) => sqlx::query("SELECT 1").execute(pool).await.is_ok(),
+        DatabasePool::Sqlite(pool) => sqlx::query("SELECT 1").execute(pool).await.is_ok(),
+    };
+
+    if is_healthy {
+        HttpResponse::Ok().json("Healthy")
+    } else {
+        HttpResponse::ServiceUnavailable().json("Database unavailable")
+    }
+}
+
+const MAX_GENERATE_ATTEMPTS: usize = 10;
+
+/// Asks the configured generator for codes until one is free to use. The
+/// insert reserves the code, drawing another one if it was taken meanwhile.
+async fn generate_short_code(state: &AppState, user_id: i32) -> Result<String, AppError> {
+    for _ in 0..MAX_GENERATE_ATTEMPTS {
+        let code = state.codes.candidate(&state.db).await?;
+        if validate_custom_code(&code).is_ok()
+            && !code_taken(&state.db, &code).await?
+            && !code_retired(&state.db, &code, user_id).await?
+        {
+            return Ok(code);
+        }
+    }
+    Err(no_free_code())
+}
+
+fn no_free_code() -> AppError {
+    tracing::warn!(
+        "No free short code after {} attempts, consider a longer SHORT_CODE_LENGTH",
+        MAX_GENERATE_ATTEMPTS
+    );
+    AppError::InvalidInput(
+        "Couldn't generate a free short code, please try again or pick a custom code".to_string(),
+    )
+}
+
+pub async fn register(
+    state: web::Data<AppState>,
+    payload: web::Json<RegisterRequest>,
+) -> Result<impl Responder, AppError> {
+    // Check if any users exist
+    let user_count = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            let mut tx = pool.begin().await?;
+            let count = sqlx::query_as::<Postgres, (i64,)>("SELECT COUNT(*)::bigint FROM users")
+                .fetch_one(&mut *tx)
+                .await?
+                .0;
+            tx.commit().await?;
+            count
+        }
+        DatabasePool::Sqlite(pool) => {
+            let mut tx = pool.begin().await?;
+            let count = sqlx::query_as::<Sqlite, (i64,)>("SELECT COUNT(*) FROM users")
+                .fetch_one(&mut *tx)
+                .await?
+                .0;
+            tx.commit().await?;
+            count
+        }
+    };
+
+    // If users exist, registration is closed - no exceptions
+    if user_count > 0 {
+        return Err(AppError::Auth("Registration is closed".to_string()));
+    }
+
+    // Verify admin token for first user
+    match (&state.admin_token, &payload.admin_token) {
+        (Some(stored_token), Some(provided_token)) if stored_token == provided_token => {
+            // Token matches, proceed with registration
+        }
+        _ => return Err(AppError::Auth("Invalid admin setup token".to_string())),
+    }
+
+    // Check if email already exists
+    let exists = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            let mut tx = pool.begin().await?;
+            let exists =
+                sqlx::query_as::<Postgres, (i32,)>("SELECT id FROM users WHERE email = $1")
+                    .bind(&payload.email)
+                    .fetch_optional(&mut *tx)
+                    .await?;
+            tx.commit().await?;
+            exists
+        }
+        DatabasePool::Sqlite(pool) => {
+            let mut tx = pool.begin().await?;
+            let exists = sqlx::query_as::<Sqlite, (i32,)>("SELECT id FROM users WHERE email = ?")
+                .bind(&payload.email)
+                .fetch_optional(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            exists
+        }
+    };
+
+    if exists.is_some() {
+        return Err(AppError::Auth("Email already registered".to_string()));
+    }
+
+    let salt = SaltString::generate(&mut OsRng);
+    let argon2 = Argon2::default();
+    let password_hash = argon2
+        .hash_password(payload.password.as_bytes(), &salt)
+        .map_err(|e| AppError::Auth(e.to_string()))?
+        .to_string();
+
+    // Insert new user
+    let user = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            let mut tx = pool.begin().await?;
+            let user = sqlx::query_as::<Postgres, User>(
+                "INSERT INTO users (email, password_hash, is_admin) VALUES ($1, $2, TRUE) RETURNING *",
+            )
+            .bind(&payload.email)
+            .bind(&password_hash)
+            .fetch_one(&mut *tx)
+            .await?;
+            tx.commit().await?;
+            user
+        }
+        DatabasePool::Sqlite(pool) => {
+            let mut tx = pool.begin().await?;
+            let user = sqlx::query_as::<Sqlite, User>(
+                "INSERT INTO users (email, password_hash, is_admin) VALUES (?, ?, TRUE) RETURNING *",
+            )
+            .bind(&payload.email)
+            .bind(&password_hash)
+            .fetch_one(&mut *tx)
+            .await?;
+            tx.commit().await?;
+            user
+        }
+    };
+
+    let claims = Claims::new(user.id);
+    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "default_secret".to_string());
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )
+    .map_err(|e| AppError::Auth(e.to_string()))?;
+
+    Ok(HttpResponse::Ok().json(AuthResponse {
+        token,
+        user: UserResponse {
+            id: user.id,
+            email: user.email,
+            display_name: user.display_name,
+        },
+    }))
+}
+
+pub async fn login(
+    state: web::Data<AppState>,
+    payload: web::Json<LoginRequest>,
+) -> Result<impl Responder, AppError> {
+    let user = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            let mut tx = pool.begin().await?;
+            let user = sqlx::query_as::<Postgres, User>("SELECT * FROM users WHERE email = $1")
+                .bind(&payload.email)
+                .fetch_optional(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            user
+        }
+        DatabasePool::Sqlite(pool) => {
+            let mut tx = pool.begin().await?;
+            let user = sqlx::query_as::<Sqlite, User>("SELECT * FROM users WHERE email = ?")
+                .bind(&payload.email)
+                .fetch_optional(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            user
+        }
+    }
+    .ok_or_else(|| AppError::Auth("Invalid credentials".to_string()))?;
+
+    let argon2 = Argon2::default();
+    let parsed_hash =
+        PasswordHash::new(&user.password_hash).map_err(|e| AppError::Auth(e.to_string()))?;
+
+    if argon2
+        .verify_password(payload.password.as_bytes(), &parsed_hash)
+        .is_err()
+    {
+        return Err(AppError::Auth("Invalid credentials".to_string()));
+    }
+    if state.banned.contains(user.id) {
+        return Err(AppError::Banned);
+    }
+
+    let claims = Claims::new(user.id);
+    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "default_secret".to_string());
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )
+    .map_err(|e| AppError::Auth(e.to_string()))?;
+
+    Ok(HttpResponse::Ok().json(AuthResponse {
+        token,
+        user: UserResponse {
+            id: user.id,
+            email: user.email,
+            display_name: user.display_name,
+        },
+    }))
+}
+
+const MAX_DISPLAY_NAME_LEN: usize = 100;
+
+pub async fn update_profile(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    payload: web::Json<UpdateProfile>,
+) -> Result<impl Responder, AppError> {
+    // An empty string clears the display name, leaving it out keeps it
+    let display_name = edited_text(
+        "Display name",
+        payload.display_name.as_deref(),
+        MAX_DISPLAY_NAME_LEN,
+    )?;
+
+    let updated = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, User>(
+                "UPDATE users SET display_name = NULLIF(COALESCE($1, display_name), '') WHERE id = $2 RETURNING *",
+            )
+            .bind(&display_name)
+            .bind(user.user_id)
+            .fetch_optional(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, User>(
+                "UPDATE users SET display_name = NULLIF(COALESCE(?1, display_name), '') WHERE id = ?2 RETURNING *",
+            )
+            .bind(&display_name)
+            .bind(user.user_id)
+            .fetch_optional(pool)
+            .await?
+        }
+    }
+    .ok_or(AppError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(UserResponse {
+        id: updated.id,
+        email: updated.email,
+        display_name: updated.display_name,
+    }))
+}
+
+pub async fn get_link(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<impl Responder, AppError> {
+    let link_id = path.into_inner();
+
+    let link = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, Link>(
+                "SELECT * FROM links WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL",
+            )
+            .bind(link_id)
+            .bind(user.user_id)
+            .fetch_optional(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, Link>(
+                "SELECT * FROM links WHERE id = ?1 AND user_id = ?2 AND deleted_at IS NULL",
+            )
+            .bind(link_id)
+            .bind(user.user_id)
+            .fetch_optional(pool)
+            .await?
+        }
+    };
+    let Some(mut link) = link else {
+        return Err(AppError::NotFound);
+    };
+    attach_tags(&state.db, vec![&mut link]).await?;
+
+    Ok(HttpResponse::Ok()
+        .append_header(("ETag", link_etag(&link)))
+        .json(link))
+}
+
+/// A QR code for one of the user's links. Scans are recorded with the
+/// `qr` source.
+pub async fn get_link_qr(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+    query: web::Query<QrQuery>,
+    req: HttpRequest,
+) -> Result<impl Responder, AppError> {
+    let link_id = path.into_inner();
+    let style = QrStyle::new(&query, &state.qr_logo)?;
+
+    let code = match &state.db {
+        DatabasePool::Postgres(pool) => sqlx::query_as::<_, (String,)>(
+            "SELECT short_code FROM links WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL",
+        )
+        .bind(link_id)
+        .bind(user.user_id)
+        .fetch_optional(pool)
+        .await?,
+        DatabasePool::Sqlite(pool) => sqlx::query_as::<_, (String,)>(
+            "SELECT short_code FROM links WHERE id = ?1 AND user_id = ?2 AND deleted_at IS NULL",
+        )
+        .bind(link_id)
+        .bind(user.user_id)
+        .fetch_optional(pool)
+        .await?,
+    };
+    let Some((code,)) = code else {
+        return Err(AppError::NotFound);
+    };
+
+    let url = format!("{}?source=qr", short_url(&state, &req, &code));
+    let mut response = style.respond(url).await?;
+    response.headers_mut().insert(
+        actix_web::http::header::CACHE_CONTROL,
+        actix_web::http::header::HeaderValue::from_static("private, max-age=3600"),
+    );
+    Ok(response)
+}
+
+/// The public QR code at `/{short_code}.qr`, for any code or alias of an
+/// enabled link.
+pub async fn public_link_qr(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<QrQuery>,
+    req: HttpRequest,
+) -> Result<impl Responder, AppError> {
+    let short_code = path.into_inner();
+    let style = QrStyle::new_public(&query, &state.qr_logo)?;
+    match find_link_by_code(&state.db, &short_code).await? {
+        Some(link) if link.enabled => {}
+        _ => return Err(AppError::NotFound),
+    }
+
+    let url = format!("{}?source=qr", short_url(&state, &req, &short_code));
+    let mut response = style.respond(url).await?;
+    // Without a configured host the code encodes whatever host the request
+    // named, which shared caches mustn't hand to anyone else
+    let cache = if state.destinations.base_url().is_some() {
+        "public, max-age=86400"
+    } else {
+        "private, max-age=3600"
+    };
+    response.headers_mut().insert(
+        actix_web::http::header::CACHE_CONTROL,
+        actix_web::http::header::HeaderValue::from_static(cache),
+    );
+    Ok(response)
+}
+
+/// Disables a link so that it stops redirecting, keeping it and its clicks.
+/// Owners and admins can disable a link.
+pub async fn disable_link(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+    payload: Option<web::Json<ModerationRequest>>,
+) -> Result<impl Responder, AppError> {
+    let reason = payload.and_then(|payload| payload.into_inner().reason);
+    let reason = normalize_text("Reason", reason.as_deref(), MAX_REPORT_REASON_LEN)?;
+    set_link_enabled(&state, user.user_id, path.into_inner(), false, reason).await
+}
+
+/// Re-enables a link. Owners can only undo their own disabling, links an
+/// admin or a blocklist disabled need an admin.
+pub async fn enable_link(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<impl Responder, AppError> {
+    set_link_enabled(&state, user.user_id, path.into_inner(), true, None).await
+}
+
+async fn set_link_enabled(
+    state: &AppState,
+    user_id: i32,
+    link_id: i32,
+    enabled: bool,
+    reason: Option<String>,
+) -> Result<HttpResponse, AppError> {
+    let link = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, Link>("SELECT * FROM links WHERE id = $1 AND deleted_at IS NULL")
+                .bind(link_id)
+                .fetch_optional(pool)
+                .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, Link>("SELECT * FROM links WHERE id = ?1 AND deleted_at IS NULL")
+                .bind(link_id)
+                .fetch_optional(pool)
+                .await?
+        }
+    };
+    let Some(link) = link else {
+        return Err(AppError::NotFound);
+    };
+    if link.user_id != Some(user_id) || (!link.enabled && link.disabled_by != Some(user_id)) {
+        // Other users' links don't exist as far as non-admins are concerned
+        let admin = is_admin(&state.db, user_id).await?;
+        if !admin && link.user_id != Some(user_id) {
+            return Err(AppError::NotFound);
+        }
+        if !admin {
+            return Err(AppError::Forbidden);
+        }
+    }
+
+    let mut link = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, Link>(
+                r#"
+                UPDATE links
+                SET enabled = $2,
+                    disabled_reason = $3,
+                    disabled_at = CASE WHEN $2 THEN NULL ELSE COALESCE(disabled_at, NOW()) END,
+                    disabled_by = CASE WHEN $2 THEN NULL ELSE $4 END,
+                    version = version + 1
+                WHERE id = $1
+                RETURNING *
+                "#,
+            )
+            .bind(link_id)
+            .bind(enabled)
+            .bind(&reason)
+            .bind(user_id)
+            .fetch_one(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, Link>(
+                r#"
+                UPDATE links
+                SET enabled = ?2,
+                    disabled_reason = ?3,
+                    disabled_at = CASE WHEN ?2 THEN NULL
+                        ELSE COALESCE(disabled_at, CURRENT_TIMESTAMP) END,
+                    disabled_by = CASE WHEN ?2 THEN NULL ELSE ?4 END,
+                    version = version + 1
+                WHERE id = ?1
+                RETURNING *
+                "#,
+            )
+            .bind(link_id)
+            .bind(enabled)
+            .bind(&reason)
+            .bind(user_id)
+            .fetch_one(pool)
+            .await?
+        }
+    };
+    attach_tags(&state.db, vec![&mut link]).await?;
+
+    Ok(HttpResponse::Ok()
+        .append_header(("ETag", link_etag(&link)))
+        .json(link))
+}
+
+/// Applies a partial update. With `If-Match`, the update only goes through
+/// if nobody else changed the link since the caller fetched it.
+pub async fn edit_link(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+    req: HttpRequest,
+    payload: web::Json<UpdateLink>,
+) -> Result<impl Responder, AppError> {
+    let link_id: i32 = path.into_inner();
+
+    // Validate the new URL if provided
+    let url = match payload.url.as_deref() {
+        Some(url) => Some(
+            state
+                .destinations
+                .check(&state.db, user.user_id, url)
+                .await?,
+        ),
+        None => None,
+    };
+    let tags = payload.tags.as_deref().map(normalize_tags).transpose()?;
+    // An empty string clears a field, leaving it out keeps the current value
+    let title = edited_text("Title", payload.title.as_deref(), MAX_TITLE_LEN)?;
+    let description = edited_text(
+        "Description",
+        payload.description.as_deref(),
+        MAX_DESCRIPTION_LEN,
+    )?;
+    let notes = edited_text("Notes", payload.notes.as_deref(), MAX_NOTES_LEN)?;
+    let og_title = edited_text(
+        "Open Graph title",
+        payload.og_title.as_deref(),
+        MAX_TITLE_LEN,
+    )?;
+    let og_description = edited_text(
+        "Open Graph description",
+        payload.og_description.as_deref(),
+        MAX_DESCRIPTION_LEN,
+    )?;
+    let og_image = normalize_og_image(payload.og_image.as_deref())?;
+
+    // Validate custom code if provided
+    if let Some(ref custom_code) = payload.custom_code {
+        validate_custom_code(custom_code)?;
+
+        // Check if the custom code is already taken by another link. The
+        // link's own aliases are fine, renaming back to an old code swaps them.
+        let existing_link = code_owner(&state.db, custom_code).await?;
+        if matches!(existing_link, Some((id, _)) if id != link_id) {
+            return Err(AppError::InvalidInput(
+                "Custom code already taken".to_string(),
+            ));
+        }
+        if code_retired(&state.db, custom_code, user.user_id).await? {
+            return Err(AppError::InvalidInput(CODE_RETIRED_MESSAGE.to_string()));
+        }
+    }
+
+    // Update the link
+    let mut updated_link = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            let mut tx = pool.begin().await?;
+
+            // First verify the link belongs to the user and isn't in the trash
+            let link = sqlx::query_as::<_, Link>(
+                "SELECT * FROM links WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL",
+            )
+            .bind(link_id)
+            .bind(user.user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            let Some(link) = link else {
+                return Err(AppError::NotFound);
+            };
+            check_if_match(&req, &link)?;
+
+            // Update the link, unless someone else got there first
+            let updated = sqlx::query_as::<_, Link>(
+                r#"
+                UPDATE links 
+                SET 
+                    original_url = COALESCE($1, original_url),
+                    short_code = COALESCE($2, short_code),
+                    title = NULLIF(COALESCE($5, title), ''),
+                    description = NULLIF(COALESCE($6, description), ''),
+                    notes = NULLIF(COALESCE($7, notes), ''),
+                    preview = COALESCE($9, preview),
+                    og_title = NULLIF(COALESCE($10, og_title), ''),
+                    og_description = NULLIF(COALESCE($11, og_description), ''),
+                    og_image = NULLIF(COALESCE($12, og_image), ''),
+                    embeddable = COALESCE($13, embeddable),
+                    public_stats = COALESCE($14, public_stats),
+                    flagged_at = CASE WHEN $1 IS NULL OR NOT enabled THEN flagged_at END,
+                    flag_reason = CASE WHEN $1 IS NULL OR NOT enabled THEN flag_reason END,
+                    version = version + 1
+                WHERE id = $3 AND user_id = $4 AND version = $8
+                RETURNING *
+                "#,
+            )
+            .bind(&url)
+            .bind(&payload.custom_code)
+            .bind(link_id)
+            .bind(user.user_id)
+            .bind(&title)
+            .bind(&description)
+            .bind(&notes)
+            .bind(link.version)
+            .bind(payload.preview)
+            .bind(&og_title)
+            .bind(&og_description)
+            .bind(&og_image)
+            .bind(payload.embeddable)
+            .bind(payload.public_stats)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(AppError::PreconditionFailed)?;
+
+            // The old code stays in `link_codes` and keeps redirecting as an
+            // alias. Renaming back to an alias reuses its row.
+            if updated.short_code != link.short_code {
+                sqlx::query(
+                    r#"
+                    INSERT INTO link_codes (short_code, link_id)
+                    SELECT $1, $2
+                    WHERE NOT EXISTS (SELECT 1 FROM link_codes WHERE short_code = $1 AND link_id = $2)
+                    "#,
+                )
+                .bind(&updated.short_code)
+                .bind(link_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(code_write_error)?;
+                // The old code counts against the same cap as added aliases
+                let (count,) = sqlx::query_as::<_, (i64,)>(
+                    r#"
+                    SELECT COUNT(*) FROM link_codes
+                    JOIN links ON links.id = link_codes.link_id
+                    WHERE link_codes.link_id = $1 AND link_codes.short_code <> links.short_code
+                    "#,
+                )
+                .bind(link_id)
+                .fetch_one(&mut *tx)
+                .await?;
+                if count > MAX_ALIASES_PER_LINK {
+                    return Err(too_many_aliases());
+                }
+            }
+            if updated.short_code != link.short_code || updated.original_url != link.original_url {
+                sqlx::query(
+                    r#"
+                    INSERT INTO link_revisions (link_id, user_id, old_url, new_url, old_code, new_code)
+                    VALUES ($1, $2, $3, $4, $5, $6)
+                    "#,
+                )
+                .bind(link_id)
+                .bind(user.user_id)
+                .bind(&link.original_url)
+                .bind(&updated.original_url)
+                .bind(&link.short_code)
+                .bind(&updated.short_code)
+                .execute(&mut *tx)
+                .await?;
+            }
+
+            if let Some(ref tags) = tags {
+                set_link_tags_pg(&mut tx, user.user_id, link_id, tags).await?;
+            }
+
+            tx.commit().await?;
+            updated
+        }
+        DatabasePool::Sqlite(pool) => {
+            let mut tx = pool.begin().await?;
+
+            // First verify the link belongs to the user and isn't in the trash
+            let link = sqlx::query_as::<_, Link>(
+                "SELECT * FROM links WHERE id = ?1 AND user_id = ?2 AND deleted_at IS NULL",
+            )
+            .bind(link_id)
+            .bind(user.user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            let Some(link) = link else {
+                return Err(AppError::NotFound);
+            };
+            check_if_match(&req, &link)?;
+
+            // Update the link, unless someone else got there first
+            let updated = sqlx::query_as::<_, Link>(
+                r#"
+                UPDATE links 
+                SET 
+                    original_url = COALESCE(?1, original_url),
+                    short_code = COALESCE(?2, short_code),
+                    title = NULLIF(COALESCE(?5, title), ''),
+                    description = NULLIF(COALESCE(?6, description), ''),
+                    notes = NULLIF(COALESCE(?7, notes), ''),
+                    preview = COALESCE(?9, preview),
+                    og_title = NULLIF(COALESCE(?10, og_title), ''),
+                    og_description = NULLIF(COALESCE(?11, og_description), ''),
+                    og_image = NULLIF(COALESCE(?12, og_image), ''),
+                    embeddable = COALESCE(?13, embeddable),
+                    public_stats = COALESCE(?14, public_stats),
+                    flagged_at = CASE WHEN ?1 IS NULL OR NOT enabled THEN flagged_at END,
+                    flag_reason = CASE WHEN ?1 IS NULL OR NOT enabled THEN flag_reason END,
+                    version = version + 1
+                WHERE id = ?3 AND user_id = ?4 AND version = ?8
+                RETURNING *
+                "#,
+            )
+            .bind(&url)
+            .bind(&payload.custom_code)
+            .bind(link_id)
+            .bind(user.user_id)
+            .bind(&title)
+            .bind(&description)
+            .bind(&notes)
+            .bind(link.version)
+            .bind(payload.preview)
+            .bind(&og_title)
+            .bind(&og_description)
+            .bind(&og_image)
+            .bind(payload.embeddable)
+            .bind(payload.public_stats)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(AppError::PreconditionFailed)?;
+
+            // The old code stays in `link_codes` and keeps redirecting as an
+            // alias. Renaming back to an alias reuses its row.
+            if updated.short_code != link.short_code {
+                sqlx::query(
+                    r#"
+                    INSERT INTO link_codes (short_code, link_id)
+                    SELECT ?1, ?2
+                    WHERE NOT EXISTS (SELECT 1 FROM link_codes WHERE short_code = ?1 AND link_id = ?2)
+                    "#,
+                )
+                .bind(&updated.short_code)
+                .bind(link_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(code_write_error)?;
+                // The old code counts against the same cap as added aliases
+                let (count,) = sqlx::query_as::<_, (i64,)>(
+                    r#"
+                    SELECT COUNT(*) FROM link_codes
+                    JOIN links ON links.id = link_codes.link_id
+                    WHERE link_codes.link_id = ?1 AND link_codes.short_code <> links.short_code
+                    "#,
+                )
+                .bind(link_id)
+                .fetch_one(&mut *tx)
+                .await?;
+                if count > MAX_ALIASES_PER_LINK {
+                    return Err(too_many_aliases());
+                }
+            }
+            if updated.short_code != link.short_code || updated.original_url != link.original_url {
+                sqlx::query(
+                    r#"
+                    INSERT INTO link_revisions (link_id, user_id, old_url, new_url, old_code, new_code)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                    "#,
+                )
+                .bind(link_id)
+                .bind(user.user_id)
+                .bind(&link.original_url)
+                .bind(&updated.original_url)
+                .bind(&link.short_code)
+                .bind(&updated.short_code)
+                .execute(&mut *tx)
+                .await?;
+            }
+
+            if let Some(ref tags) = tags {
+                set_link_tags_sqlite(&mut tx, user.user_id, link_id, tags).await?;
+            }
+
+            tx.commit().await?;
+            updated
+        }
+    };
+
+    attach_tags(&state.db, vec![&mut updated_link]).await?;
+
+    Ok(HttpResponse::Ok()
+        .append_header(("ETag", link_etag(&updated_link)))
+        .json(updated_link))
+}
+
+/// Moves a link to the trash. It stops redirecting but keeps its clicks
+/// until it is restored or purged.
+pub async fn delete_link(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<impl Responder, AppError> {
+    let link_id: i32 = path.into_inner();
+
+    let result = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query(
+                "UPDATE links SET deleted_at = NOW() WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL",
+            )
+            .bind(link_id)
+            .bind(user.user_id)
+            .execute(pool)
+            .await?
+            .rows_affected()
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query(
+                "UPDATE links SET deleted_at = CURRENT_TIMESTAMP WHERE id = ? AND user_id = ? AND deleted_at IS NULL",
+            )
+            .bind(link_id)
+            .bind(user.user_id)
+            .execute(pool)
+            .await?
+            .rows_affected()
+        }
+    };
+
+    if result == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn list_trash(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+) -> Result<impl Responder, AppError> {
+    let mut links = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, Link>(
+                "SELECT * FROM links WHERE user_id = $1 AND deleted_at IS NOT NULL ORDER BY deleted_at DESC, id DESC",
+            )
+            .bind(user.user_id)
+            .fetch_all(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, Link>(
+                "SELECT * FROM links WHERE user_id = ? AND deleted_at IS NOT NULL ORDER BY deleted_at DESC, id DESC",
+            )
+            .bind(user.user_id)
+            .fetch_all(pool)
+            .await?
+        }
+    };
+    attach_tags(&state.db, links.iter_mut().collect()).await?;
+
+    Ok(HttpResponse::Ok().json(links))
+}
+
+pub async fn restore_link(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<impl Responder, AppError> {
+    let link_id: i32 = path.into_inner();
+
+    let link = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, Link>(
+                r#"
+                UPDATE links SET deleted_at = NULL
+                WHERE id = $1 AND user_id = $2 AND deleted_at IS NOT NULL
+                RETURNING *
+                "#,
+            )
+            .bind(link_id)
+            .bind(user.user_id)
+            .fetch_optional(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, Link>(
+                r#"
+                UPDATE links SET deleted_at = NULL
+                WHERE id = ?1 AND user_id = ?2 AND deleted_at IS NOT NULL
+                RETURNING *
+                "#,
+            )
+            .bind(link_id)
+            .bind(user.user_id)
+            .fetch_optional(pool)
+            .await?
+        }
+    };
+
+    let Some(mut link) = link else {
+        return Err(AppError::NotFound);
+    };
+    attach_tags(&state.db, vec![&mut link]).await?;
+
+    Ok(HttpResponse::Ok().json(link))
+}
+
+pub async fn get_link_clicks(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<impl Responder, AppError> {
+    let link_id = path.into_inner();
+
+    // First verify the link belongs to the user
+    let link = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, (i32,)>("SELECT id FROM links WHERE id = $1 AND user_id = $2")
+                .bind(link_id)
+                .bind(user.user_id)
+                .fetch_optional(pool)
+                .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, (i32,)>("SELECT id FROM links WHERE id = ? AND user_id = ?")
+                .bind(link_id)
+                .bind(user.user_id)
+                .fetch_optional(pool)
+                .await?
+        }
+    };
+
+    if link.is_none() {
+        return Err(AppError::NotFound);
+    }
+
+    let clicks = daily_clicks(&state.db, link_id).await?;
+
+    Ok(HttpResponse::Ok().json(clicks))
+}
+
+/// A link's clicks per day, oldest first. Days without clicks are left out.
+async fn daily_clicks(db: &DatabasePool, link_id: i32) -> Result<Vec<ClickStats>, AppError> {
+    let clicks = match db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, ClickStats>(
+                r#"
+                SELECT 
+                    DATE(created_at)::text as date,
+                    COUNT(*)::bigint as clicks
+                FROM clicks
+                WHERE link_id = $1
+                GROUP BY DATE(created_at)
+                ORDER BY DATE(created_at) ASC
+                "#,
+            )
+            .bind(link_id)
+            .fetch_all(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, ClickStats>(
+                r#"
+                SELECT 
+                    DATE(created_at) as date,
+                    COUNT(*) as clicks
+                FROM clicks
+                WHERE link_id = ?
+                GROUP BY DATE(created_at)
+                ORDER BY DATE(created_at) ASC
+                "#,
+            )
+            .bind(link_id)
+            .fetch_all(pool)
+            .await?
+        }
+    };
+    Ok(clicks)
+}
+
+/// The public click badge of a link whose owner made it embeddable.
+pub async fn get_link_badge(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<BadgeQuery>,
+    req: HttpRequest,
+) -> Result<impl Responder, AppError> {
+    let link = embeddable_link(&state.db, &path.into_inner()).await?;
+    let clicks = daily_clicks(&state.db, link.id).await?;
+    let total = clicks.iter().map(|day| day.clicks).sum();
+    let svg = embeds::badge(query.label.as_deref(), query.color.as_deref(), total)?;
+    Ok(embeds::svg_response(&req, svg))
+}
+
+/// The public sparkline of daily clicks of a link whose owner made it
+/// embeddable.
+pub async fn get_link_sparkline(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<SparklineQuery>,
+    req: HttpRequest,
+) -> Result<impl Responder, AppError> {
+    let link = embeddable_link(&state.db, &path.into_inner()).await?;
+    let clicks = daily_clicks(&state.db, link.id).await?;
+    let svg = embeds::sparkline(&query, &clicks, chrono::Utc::now().date_naive())?;
+    Ok(embeds::svg_response(&req, svg))
+}
+
+const STATS_PAGE_DAYS: u32 = 30;
+
+/// A link's stats page at `/{short_code}/stats`, public if the owner set
+/// `public_stats` or the request carries a valid share token for the link.
+/// Disabled links have no stats page.
+pub async fn public_link_stats(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<PublicStatsQuery>,
+) -> Result<impl Responder, AppError> {
+    let link = match find_link_by_code(&state.db, &path.into_inner()).await? {
+        Some(link) if link.enabled => link,
+        _ => return Err(AppError::NotFound),
+    };
+    let shared = query
+        .share
+        .as_deref()
+        .is_some_and(|token| share_token_valid(token, &link));
+    if !link.public_stats && !shared {
+        return Err(AppError::NotFound);
+    }
+
+    let clicks = daily_clicks(&state.db, link.id).await?;
+    let stats = PublicStats {
+        short_code: link.short_code,
+        title: link.title,
+        total_clicks: clicks.iter().map(|day| day.clicks).sum(),
+        clicks,
+        sources: daily_sources(&state.db, link.id).await?,
+    };
+    if query.format == StatsFormat::Json {
+        return Ok(HttpResponse::Ok()
+            .insert_header(("Cache-Control", "private, max-age=60"))
+            .json(stats));
+    }
+
+    let sparkline = embeds::sparkline(
+        &SparklineQuery {
+            days: Some(STATS_PAGE_DAYS),
+            width: Some(640),
+            height: Some(80),
+            color: None,
+        },
+        &stats.clicks,
+        chrono::Utc::now().date_naive(),
+    )?;
+    let mut days = String::new();
+    for day in stats.clicks.iter().rev() {
+        days.push_str(&format!(
+            r#"<tr><td>{}</td><td class="count">{}</td></tr>"#,
+            pages::escape_html(&day.date),
+            day.clicks
+        ));
+    }
+    let mut totals: Vec<(&str, i64)> = Vec::new();
+    for row in &stats.sources {
+        match totals.iter_mut().find(|(source, _)| *source == row.source) {
+            Some((_, count)) => *count += row.count,
+            None => totals.push((&row.source, row.count)),
+        }
+    }
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    let mut sources = String::new();
+    for (source, count) in totals {
+        sources.push_str(&format!(
+            r#"<tr><td>{}</td><td class="count">{}</td></tr>"#,
+            pages::escape_html(source),
+            count
+        ));
+    }
+
+    Ok(StatsPage {
+        short_code: &stats.short_code,
+        title: stats.title.as_deref(),
+        total_clicks: &stats.total_clicks.to_string(),
+        sparkline: &sparkline,
+        days: Some(days.as_str()).filter(|rows| !rows.is_empty()),
+        sources: Some(sources.as_str()).filter(|rows| !rows.is_empty()),
+    }
+    .render())
+}
+
+const SHARE_TOKEN_AUDIENCE: &str = "stats-share";
+
+fn stats_share_token(claims: &StatsShareClaims) -> Result<String, AppError> {
+    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "default_secret".to_string());
+    encode(
+        &Header::default(),
+        claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )
+    .map_err(|e| AppError::Auth(e.to_string()))
+}
+
+/// Whether `token` is a share token for `link` that hasn't been revoked.
+fn share_token_valid(token: &str, link: &Link) -> bool {
+    let Some(ref nonce) = link.stats_share_nonce else {
+        return false;
+    };
+    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "default_secret".to_string());
+    // Share tokens don't expire, they are revoked by replacing the nonce
+    let mut validation = Validation::default();
+    validation.required_spec_claims = HashSet::from(["aud".to_string()]);
+    validation.validate_exp = false;
+    validation.set_audience(&[SHARE_TOKEN_AUDIENCE]);
+    match decode::<StatsShareClaims>(
+        token,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &validation,
+    ) {
+        Ok(data) => data.claims.link == link.id && data.claims.nonce == *nonce,
+        Err(_) => false,
+    }
+}
+
+fn stats_share(link: &Link) -> Result<Option<StatsShare>, AppError> {
+    let Some(ref nonce) = link.stats_share_nonce else {
+        return Ok(None);
+    };
+    let token = stats_share_token(&StatsShareClaims {
+        aud: SHARE_TOKEN_AUDIENCE.to_string(),
+        link: link.id,
+        nonce: nonce.clone(),
+    })?;
+    let url = format!("/{}/stats?share={}", link.short_code, token);
+    Ok(Some(StatsShare { token, url }))
+}
+
+/// The current share link for a link's stats page.
+pub async fn get_link_stats_share(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<impl Responder, AppError> {
+    let link_id = path.into_inner();
+    let link = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, Link>(
+                "SELECT * FROM links WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL",
+            )
+            .bind(link_id)
+            .bind(user.user_id)
+            .fetch_optional(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, Link>(
+                "SELECT * FROM links WHERE id = ?1 AND user_id = ?2 AND deleted_at IS NULL",
+            )
+            .bind(link_id)
+            .bind(user.user_id)
+            .fetch_optional(pool)
+            .await?
+        }
+    };
+    match link.as_ref().map(stats_share).transpose()?.flatten() {
+        Some(share) => Ok(HttpResponse::Ok().json(share)),
+        None => Err(AppError::NotFound),
+    }
+}
+
+/// Creates a share link for a link's stats page, revoking any earlier one.
+pub async fn share_link_stats(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<impl Responder, AppError> {
+    let link_id = path.into_inner();
+    let nonce: String = (0..16)
+        .map(|_| format!("{:02x}", rand::random::<u8>()))
+        .collect();
+
+    let link = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, Link>(
+                r#"
+                UPDATE links SET stats_share_nonce = $1
+                WHERE id = $2 AND user_id = $3 AND deleted_at IS NULL
+                RETURNING *
+                "#,
+            )
+            .bind(&nonce)
+            .bind(link_id)
+            .bind(user.user_id)
+            .fetch_optional(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, Link>(
+                r#"
+                UPDATE links SET stats_share_nonce = ?1
+                WHERE id = ?2 AND user_id = ?3 AND deleted_at IS NULL
+                RETURNING *
+                "#,
+            )
+            .bind(&nonce)
+            .bind(link_id)
+            .bind(user.user_id)
+            .fetch_optional(pool)
+            .await?
+        }
+    };
+    let Some(link) = link else {
+        return Err(AppError::NotFound);
+    };
+    Ok(HttpResponse::Created().json(stats_share(&link)?))
+}
+
+/// Revokes the share link of a link's stats page.
+pub async fn revoke_link_stats_share(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<impl Responder, AppError> {
+    let link_id = path.into_inner();
+    let result = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query(
+                "UPDATE links SET stats_share_nonce = NULL WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL",
+            )
+            .bind(link_id)
+            .bind(user.user_id)
+            .execute(pool)
+            .await?
+            .rows_affected()
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query(
+                "UPDATE links SET stats_share_nonce = NULL WHERE id = ?1 AND user_id = ?2 AND deleted_at IS NULL",
+            )
+            .bind(link_id)
+            .bind(user.user_id)
+            .execute(pool)
+            .await?
+            .rows_affected()
+        }
+    };
+    if result == 0 {
+        return Err(AppError::NotFound);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// The link behind an embed, which has to be embeddable and enabled.
+async fn embeddable_link(db: &DatabasePool, code: &str) -> Result<Link, AppError> {
+    match find_link_by_code(db, code).await? {
+        Some(link) if link.embeddable && link.enabled => Ok(link),
+        _ => Err(AppError::NotFound),
+    }
+}
+
+/// Changes made to a link's destination and short code, newest first.
+pub async fn get_link_history(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<impl Responder, AppError> {
+    let link_id = path.into_inner();
+
+    // First verify the link belongs to the user
+    if !link_owned_by(&state.db, link_id, user.user_id).await? {
+        return Err(AppError::NotFound);
+    }
+
+    let revisions =
+        match &state.db {
+            DatabasePool::Postgres(pool) => sqlx::query_as::<_, LinkRevision>(
+                "SELECT * FROM link_revisions WHERE link_id = $1 ORDER BY created_at DESC, id DESC",
+            )
+            .bind(link_id)
+            .fetch_all(pool)
+            .await?,
+            DatabasePool::Sqlite(pool) => sqlx::query_as::<_, LinkRevision>(
+                "SELECT * FROM link_revisions WHERE link_id = ?1 ORDER BY created_at DESC, id DESC",
+            )
+            .bind(link_id)
+            .fetch_all(pool)
+            .await?,
+        };
+
+    Ok(HttpResponse::Ok().json(revisions))
+}
+
+const MAX_ALIASES_PER_LINK: i64 = 20;
+
+/// Lists every code that resolves to a link, its short code first, with the
+/// clicks that came in through each.
+pub async fn list_link_codes(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<impl Responder, AppError> {
+    let link_id = path.into_inner();
+
+    // First verify the link belongs to the user
+    if !link_owned_by(&state.db, link_id, user.user_id).await? {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(HttpResponse::Ok().json(link_codes(&state.db, link_id).await?))
+}
+
+async fn link_codes(db: &DatabasePool, link_id: i32) -> Result<Vec<LinkCode>, AppError> {
+    let codes = match db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, LinkCode>(
+                r#"
+                SELECT link_codes.short_code,
+                    link_codes.short_code = links.short_code AS is_primary,
+                    link_codes.created_at,
+                    (SELECT COUNT(*) FROM clicks
+                     WHERE clicks.link_id = link_codes.link_id AND clicks.short_code = link_codes.short_code) AS clicks
+                FROM link_codes
+                JOIN links ON links.id = link_codes.link_id
+                WHERE link_codes.link_id = $1
+                ORDER BY is_primary DESC, link_codes.created_at ASC
+                "#,
+            )
+            .bind(link_id)
+            .fetch_all(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, LinkCode>(
+                r#"
+                SELECT link_codes.short_code,
+                    link_codes.short_code = links.short_code AS is_primary,
+                    link_codes.created_at,
+                    (SELECT COUNT(*) FROM clicks
+                     WHERE clicks.link_id = link_codes.link_id AND clicks.short_code = link_codes.short_code) AS clicks
+                FROM link_codes
+                JOIN links ON links.id = link_codes.link_id
+                WHERE link_codes.link_id = ?1
+                ORDER BY is_primary DESC, link_codes.created_at ASC
+                "#,
+            )
+            .bind(link_id)
+            .fetch_all(pool)
+            .await?
+        }
+    };
+    Ok(codes)
+}
+
+/// Adds another short code that redirects to the link and counts towards its
+/// clicks.
+pub async fn add_link_alias(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+    payload: web::Json<AliasRequest>,
+) -> Result<impl Responder, AppError> {
+    let link_id = path.into_inner();
+    let code = &payload.short_code;
+    validate_custom_code(code)?;
+
+    // First verify the link belongs to the user and isn't in the trash
+    let link = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, (i32,)>(
+                "SELECT id FROM links WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL",
+            )
+            .bind(link_id)
+            .bind(user.user_id)
+            .fetch_optional(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, (i32,)>(
+                "SELECT id FROM links WHERE id = ?1 AND user_id = ?2 AND deleted_at IS NULL",
+            )
+            .bind(link_id)
+            .bind(user.user_id)
+            .fetch_optional(pool)
+            .await?
+        }
+    };
+    if link.is_none() {
+        return Err(AppError::NotFound);
+    }
+
+    if code_taken(&state.db, code).await? {
+        return Err(AppError::InvalidInput(
+            "Custom code already taken".to_string(),
+        ));
+    }
+    if code_retired(&state.db, code, user.user_id).await? {
+        return Err(AppError::InvalidInput(CODE_RETIRED_MESSAGE.to_string()));
+    }
+
+    match &state.db {
+        DatabasePool::Postgres(pool) => {
+            let mut tx = pool.begin().await?;
+            // Aliases are the link's codes besides its own short code
+            let (count,) = sqlx::query_as::<_, (i64,)>(
+                r#"
+                SELECT COUNT(*) FROM link_codes
+                JOIN links ON links.id = link_codes.link_id
+                WHERE link_codes.link_id = $1 AND link_codes.short_code <> links.short_code
+                "#,
+            )
+            .bind(link_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            if count >= MAX_ALIASES_PER_LINK {
+                return Err(too_many_aliases());
+            }
+            sqlx::query("INSERT INTO link_codes (short_code, link_id) VALUES ($1, $2)")
+                .bind(code)
+                .bind(link_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(code_write_error)?;
+            tx.commit().await?;
+        }
+        DatabasePool::Sqlite(pool) => {
+            let mut tx = pool.begin().await?;
+            // Aliases are the link's codes besides its own short code
+            let (count,) = sqlx::query_as::<_, (i64,)>(
+                r#"
+                SELECT COUNT(*) FROM link_codes
+                JOIN links ON links.id = link_codes.link_id
+                WHERE link_codes.link_id = ?1 AND link_codes.short_code <> links.short_code
+                "#,
+            )
+            .bind(link_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            if count >= MAX_ALIASES_PER_LINK {
+                return Err(too_many_aliases());
+            }
+            sqlx::query("INSERT INTO link_codes (short_code, link_id) VALUES (?1, ?2)")
+                .bind(code)
+                .bind(link_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(code_write_error)?;
+            tx.commit().await?;
+        }
+    }
+
+    Ok(HttpResponse::Created().json(link_codes(&state.db, link_id).await?))
+}
+
+fn code_write_error(err: sqlx::Error) -> AppError {
+    match err.as_database_error() {
+        Some(db_err) if db_err.is_unique_violation() => {
+            AppError::InvalidInput("Custom code already taken".to_string())
+        }
+        _ => AppError::Database(err),
+    }
+}
+
+fn too_many_aliases() -> AppError {
+    AppError::InvalidInput(format!(
+        "A link can have at most {} aliases",
+        MAX_ALIASES_PER_LINK
+    ))
+}
+
+/// Removes an alias. The code is retired like the codes of purged links, so
+/// only the link's owner can use it again.
+pub async fn remove_link_alias(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<(i32, String)>,
+) -> Result<impl Responder, AppError> {
+    let (link_id, code) = path.into_inner();
+
+    // First verify the link belongs to the user
+    if !link_owned_by(&state.db, link_id, user.user_id).await? {
+        return Err(AppError::NotFound);
+    }
+
+    let removed = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            let mut tx = pool.begin().await?;
+            let removed = sqlx::query(
+                r#"
+                    DELETE FROM link_codes
+                    WHERE short_code = $1 AND link_id = $2
+                        AND short_code <> (SELECT short_code FROM links WHERE id = $2)
+                    "#,
+            )
+            .bind(&code)
+            .bind(link_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+            if removed > 0 {
+                sqlx::query(
+                    r#"
+                    INSERT INTO code_tombstones (short_code, user_id, expires_at)
+                    VALUES ($1, $2, NOW() + make_interval(days => $3))
+                    ON CONFLICT (short_code) DO UPDATE
+                    SET user_id = EXCLUDED.user_id,
+                        released_at = NOW(),
+                        expires_at = EXCLUDED.expires_at
+                    "#,
+                )
+                .bind(&code)
+                .bind(user.user_id)
+                .bind(state.trash.tombstone_days.map(|d| d as i32))
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+            removed
+        }
+        DatabasePool::Sqlite(pool) => {
+            let mut tx = pool.begin().await?;
+            let removed = sqlx::query(
+                r#"
+                    DELETE FROM link_codes
+                    WHERE short_code = ?1 AND link_id = ?2
+                        AND short_code <> (SELECT short_code FROM links WHERE id = ?2)
+                    "#,
+            )
+            .bind(&code)
+            .bind(link_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+            if removed > 0 {
+                sqlx::query(
+                    r#"
+                    INSERT INTO code_tombstones (short_code, user_id, expires_at)
+                    VALUES (?1, ?2,
+                        CASE WHEN ?3 IS NULL THEN NULL
+                        ELSE datetime('now', '+' || ?3 || ' days') END)
+                    ON CONFLICT (short_code) DO UPDATE
+                    SET user_id = excluded.user_id,
+                        released_at = CURRENT_TIMESTAMP,
+                        expires_at = excluded.expires_at
+                    "#,
+                )
+                .bind(&code)
+                .bind(user.user_id)
+                .bind(state.trash.tombstone_days)
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+            removed
+        }
+    };
+
+    if removed == 0 {
+        Err(AppError::NotFound)
+    } else {
+        Ok(HttpResponse::NoContent().finish())
+    }
+}
+
+async fn link_owned_by(db: &DatabasePool, link_id: i32, user_id: i32) -> Result<bool, AppError> {
+    let link = match db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, (i32,)>("SELECT id FROM links WHERE id = $1 AND user_id = $2")
+                .bind(link_id)
+                .bind(user_id)
+                .fetch_optional(pool)
+                .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, (i32,)>("SELECT id FROM links WHERE id = ?1 AND user_id = ?2")
+                .bind(link_id)
+                .bind(user_id)
+                .fetch_optional(pool)
+                .await?
+        }
+    };
+    Ok(link.is_some())
+}
 
-pub async fn health_check(state: web::Data<AppState>) -> impl Responder {
-    let is_healthy = match &state.db {
-        DatabasePool::Postgres(pool) => sqlx::query("SELECT 1").execute(pool).await.is_ok(),
-        DatabasePool::Sqlite(pool) => sqlx::query("SELECT 1").execute(pool).await.is_ok(),
+pub async fn get_link_sources(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<impl Responder, AppError> {
+    let link_id = path.into_inner();
+
+    // Verify the link belongs to the user
+    let link = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            let mut tx = pool.begin().await?;
+            let link = sqlx::query_as::<Postgres, (i32,)>(
+                "SELECT id FROM links WHERE id = $1 AND user_id = $2",
+            )
+            .bind(link_id)
+            .bind(user.user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+            tx.commit().await?;
+            link
+        }
+        DatabasePool::Sqlite(pool) => {
+            let mut tx = pool.begin().await?;
+            let link = sqlx::query_as::<Sqlite, (i32,)>(
+                "SELECT id FROM links WHERE id = ? AND user_id = ?",
+            )
+            .bind(link_id)
+            .bind(user.user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+            tx.commit().await?;
+            link
+        }
+    };
+
+    if link.is_none() {
+        return Err(AppError::NotFound);
+    }
+
+    let sources = daily_sources(&state.db, link_id).await?;
+
+    Ok(HttpResponse::Ok().json(sources))
+}
+
+/// A link's clicks per day and `source` query parameter, oldest first.
+async fn daily_sources(db: &DatabasePool, link_id: i32) -> Result<Vec<SourceStats>, AppError> {
+    let sources = match db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, SourceStats>(
+                r#"
+                SELECT 
+                    DATE(created_at)::text as date,
+                    query_source as source,
+                    COUNT(*)::bigint as count
+                FROM clicks
+                WHERE link_id = $1
+                    AND query_source IS NOT NULL
+                    AND query_source != ''
+                GROUP BY DATE(created_at), query_source
+                ORDER BY DATE(created_at) ASC, COUNT(*) DESC
+                "#,
+            )
+            .bind(link_id)
+            .fetch_all(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, SourceStats>(
+                r#"
+                SELECT 
+                    DATE(created_at) as date,
+                    query_source as source,
+                    COUNT(*) as count
+                FROM clicks
+                WHERE link_id = ?
+                    AND query_source IS NOT NULL
+                    AND query_source != ''
+                GROUP BY DATE(created_at), query_source
+                ORDER BY DATE(created_at) ASC, COUNT(*) DESC
+                "#,
+            )
+            .bind(link_id)
+            .fetch_all(pool)
+            .await?
+        }
+    };
+    Ok(sources)
+}
+
+pub async fn list_tags(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+) -> Result<impl Responder, AppError> {
+    let tags = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, TagSummary>(
+                r#"
+                SELECT
+                    tags.id,
+                    tags.name,
+                    tags.created_at,
+                    COUNT(links.id)::bigint as link_count,
+                    COALESCE(SUM(links.clicks), 0)::bigint as clicks
+                FROM tags
+                LEFT JOIN link_tags ON link_tags.tag_id = tags.id
+                LEFT JOIN links ON links.id = link_tags.link_id AND links.deleted_at IS NULL
+                WHERE tags.user_id = $1
+                GROUP BY tags.id, tags.name, tags.created_at
+                ORDER BY tags.name ASC
+                "#,
+            )
+            .bind(user.user_id)
+            .fetch_all(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, TagSummary>(
+                r#"
+                SELECT
+                    tags.id,
+                    tags.name,
+                    tags.created_at,
+                    COUNT(links.id) as link_count,
+                    COALESCE(SUM(links.clicks), 0) as clicks
+                FROM tags
+                LEFT JOIN link_tags ON link_tags.tag_id = tags.id
+                LEFT JOIN links ON links.id = link_tags.link_id AND links.deleted_at IS NULL
+                WHERE tags.user_id = ?
+                GROUP BY tags.id, tags.name, tags.created_at
+                ORDER BY tags.name ASC
+                "#,
+            )
+            .bind(user.user_id)
+            .fetch_all(pool)
+            .await?
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(tags))
+}
+
+pub async fn create_tag(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    payload: web::Json<TagRequest>,
+) -> Result<impl Responder, AppError> {
+    let name = normalize_tag(&payload.name)?;
+
+    let result = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, Tag>(
+                r#"
+                INSERT INTO tags (user_id, name) VALUES ($1, $2)
+                RETURNING id, name, created_at
+                "#,
+            )
+            .bind(user.user_id)
+            .bind(&name)
+            .fetch_one(pool)
+            .await
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, Tag>(
+                r#"
+                INSERT INTO tags (user_id, name) VALUES (?1, ?2)
+                RETURNING id, name, created_at
+                "#,
+            )
+            .bind(user.user_id)
+            .bind(&name)
+            .fetch_one(pool)
+            .await
+        }
+    };
+    let tag = result.map_err(tag_write_error)?;
+
+    Ok(HttpResponse::Created().json(tag))
+}
+
+/// Renames a tag along with the folder below it, so renaming `a` to `b`
+/// also turns `a/x` into `b/x`.
+pub async fn rename_tag(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+    payload: web::Json<TagRequest>,
+) -> Result<impl Responder, AppError> {
+    let tag_id = path.into_inner();
+    let name = normalize_tag(&payload.name)?;
+
+    let result = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            let mut tx = pool.begin().await?;
+            let old = sqlx::query_as::<_, (String,)>(
+                "SELECT name FROM tags WHERE id = $1 AND user_id = $2",
+            )
+            .bind(tag_id)
+            .bind(user.user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+            let Some((old,)) = old else {
+                return Err(AppError::NotFound);
+            };
+            let renamed = async {
+                let tag = sqlx::query_as::<_, Tag>(
+                    r#"
+                    UPDATE tags SET name = $1
+                    WHERE id = $2 AND user_id = $3
+                    RETURNING id, name, created_at
+                    "#,
+                )
+                .bind(&name)
+                .bind(tag_id)
+                .bind(user.user_id)
+                .fetch_one(&mut *tx)
+                .await?;
+                sqlx::query(
+                    r#"
+                    UPDATE tags SET name = $1 || SUBSTR(name, $2)
+                    WHERE user_id = $3 AND name LIKE $4 ESCAPE '\'
+                    "#,
+                )
+                .bind(&name)
+                .bind(old.chars().count() as i32 + 1)
+                .bind(user.user_id)
+                .bind(format!("{}/%", escape_like(&old)))
+                .execute(&mut *tx)
+                .await?;
+                Ok::<_, sqlx::Error>(tag)
+            }
+            .await;
+            if renamed.is_ok() {
+                tx.commit().await?;
+            }
+            renamed
+        }
+        DatabasePool::Sqlite(pool) => {
+            let mut tx = pool.begin().await?;
+            let old = sqlx::query_as::<_, (String,)>(
+                "SELECT name FROM tags WHERE id = ?1 AND user_id = ?2",
+            )
+            .bind(tag_id)
+            .bind(user.user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+            let Some((old,)) = old else {
+                return Err(AppError::NotFound);
+            };
+            let renamed = async {
+                let tag = sqlx::query_as::<_, Tag>(
+                    r#"
+                    UPDATE tags SET name = ?1
+                    WHERE id = ?2 AND user_id = ?3
+                    RETURNING id, name, created_at
+                    "#,
+                )
+                .bind(&name)
+                .bind(tag_id)
+                .bind(user.user_id)
+                .fetch_one(&mut *tx)
+                .await?;
+                sqlx::query(
+                    r#"
+                    UPDATE tags SET name = ?1 || SUBSTR(name, ?2)
+                    WHERE user_id = ?3 AND name LIKE ?4 ESCAPE '\'
+                    "#,
+                )
+                .bind(&name)
+                .bind(old.chars().count() as i32 + 1)
+                .bind(user.user_id)
+                .bind(format!("{}/%", escape_like(&old)))
+                .execute(&mut *tx)
+                .await?;
+                Ok::<_, sqlx::Error>(tag)
+            }
+            .await;
+            if renamed.is_ok() {
+                tx.commit().await?;
+            }
+            renamed
+        }
+    };
+    let tag = result.map_err(tag_write_error)?;
+
+    Ok(HttpResponse::Ok().json(tag))
+}
+
+pub async fn delete_tag(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<impl Responder, AppError> {
+    let tag_id = path.into_inner();
+
+    let deleted = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            let mut tx = pool.begin().await?;
+            sqlx::query(
+                "DELETE FROM link_tags WHERE tag_id IN (SELECT id FROM tags WHERE id = $1 AND user_id = $2)",
+            )
+            .bind(tag_id)
+            .bind(user.user_id)
+            .execute(&mut *tx)
+            .await?;
+            let result = sqlx::query("DELETE FROM tags WHERE id = $1 AND user_id = $2")
+                .bind(tag_id)
+                .bind(user.user_id)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            result.rows_affected()
+        }
+        DatabasePool::Sqlite(pool) => {
+            let mut tx = pool.begin().await?;
+            sqlx::query(
+                "DELETE FROM link_tags WHERE tag_id IN (SELECT id FROM tags WHERE id = ?1 AND user_id = ?2)",
+            )
+            .bind(tag_id)
+            .bind(user.user_id)
+            .execute(&mut *tx)
+            .await?;
+            let result = sqlx::query("DELETE FROM tags WHERE id = ?1 AND user_id = ?2")
+                .bind(tag_id)
+                .bind(user.user_id)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            result.rows_affected()
+        }
+    };
+
+    if deleted == 0 {
+        return Err(AppError::NotFound);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Daily clicks summed over every link carrying the tag.
+pub async fn get_tag_clicks(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<impl Responder, AppError> {
+    let tag_id = path.into_inner();
+
+    // First verify the tag belongs to the user
+    let tag = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, (i32,)>("SELECT id FROM tags WHERE id = $1 AND user_id = $2")
+                .bind(tag_id)
+                .bind(user.user_id)
+                .fetch_optional(pool)
+                .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, (i32,)>("SELECT id FROM tags WHERE id = ? AND user_id = ?")
+                .bind(tag_id)
+                .bind(user.user_id)
+                .fetch_optional(pool)
+                .await?
+        }
+    };
+
+    if tag.is_none() {
+        return Err(AppError::NotFound);
+    }
+
+    let clicks = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, ClickStats>(
+                r#"
+                SELECT 
+                    DATE(clicks.created_at)::text as date,
+                    COUNT(*)::bigint as clicks
+                FROM clicks
+                JOIN link_tags ON link_tags.link_id = clicks.link_id
+                JOIN links ON links.id = clicks.link_id AND links.deleted_at IS NULL
+                WHERE link_tags.tag_id = $1
+                GROUP BY DATE(clicks.created_at)
+                ORDER BY DATE(clicks.created_at) ASC
+                "#,
+            )
+            .bind(tag_id)
+            .fetch_all(pool)
+            .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, ClickStats>(
+                r#"
+                SELECT 
+                    DATE(clicks.created_at) as date,
+                    COUNT(*) as clicks
+                FROM clicks
+                JOIN link_tags ON link_tags.link_id = clicks.link_id
+                JOIN links ON links.id = clicks.link_id AND links.deleted_at IS NULL
+                WHERE link_tags.tag_id = ?
+                GROUP BY DATE(clicks.created_at)
+                ORDER BY DATE(clicks.created_at) ASC
+                "#,
+            )
+            .bind(tag_id)
+            .fetch_all(pool)
+            .await?
+        }
     };
 
-    if is_healthy {
-        HttpResponse::Ok().json("Healthy")
-    } else {
-        HttpResponse::ServiceUnavailable().json("Database unavailable")
-    }
+    Ok(HttpResponse::Ok().json(clicks))
 }
 
-fn generate_short_code() -> String {
-    use base62::encode;
-    use uuid::Uuid;
+fn tag_write_error(err: sqlx::Error) -> AppError {
+    match err.as_database_error() {
+        Some(db_err) if db_err.is_unique_violation() => {
+            AppError::InvalidInput("Tag already exists".to_string())
+        }
+        _ => AppError::Database(err),
+    }
+}
 
-    let uuid = Uuid::new_v4();
-    encode(uuid.as_u128() as u64).chars().take(32).collect()
+async fn require_admin(db: &DatabasePool, user_id: i32) -> Result<(), AppError> {
+    if is_admin(db, user_id).await? {
+        Ok(())
+    } else {
+        Err(AppError::Forbidden)
+    }
 }
 
-pub async fn register(
-    state: web::Data<AppState>,
-    payload: web::Json<RegisterRequest>,
-) -> Result<impl Responder, AppError> {
-    // Check if any users exist
-    let user_count = match &state.db {
+async fn is_admin(db: &DatabasePool, user_id: i32) -> Result<bool, AppError> {
+    let is_admin = match db {
         DatabasePool::Postgres(pool) => {
-            let mut tx = pool.begin().await?;
-            let count = sqlx::query_as::<Postgres, (i64,)>("SELECT COUNT(*)::bigint FROM users")
-                .fetch_one(&mut *tx)
+            sqlx::query_as::<_, (bool,)>("SELECT is_admin FROM users WHERE id = $1")
+                .bind(user_id)
+                .fetch_optional(pool)
                 .await?
-                .0;
-            tx.commit().await?;
-            count
         }
         DatabasePool::Sqlite(pool) => {
-            let mut tx = pool.begin().await?;
-            let count = sqlx::query_as::<Sqlite, (i64,)>("SELECT COUNT(*) FROM users")
-                .fetch_one(&mut *tx)
+            sqlx::query_as::<_, (bool,)>("SELECT is_admin FROM users WHERE id = ?1")
+                .bind(user_id)
+                .fetch_optional(pool)
                 .await?
-                .0;
-            tx.commit().await?;
-            count
         }
     };
 
-    // If users exist, registration is closed - no exceptions
-    if user_count > 0 {
-        return Err(AppError::Auth("Registration is closed".to_string()));
-    }
+    Ok(matches!(is_admin, Some((true,))))
+}
 
-    // Verify admin token for first user
-    match (&state.admin_token, &payload.admin_token) {
-        (Some(stored_token), Some(provided_token)) if stored_token == provided_token => {
-            // Token matches, proceed with registration
-        }
-        _ => return Err(AppError::Auth("Invalid admin setup token".to_string())),
-    }
+/// Lists retired short codes that are still blocked.
+pub async fn list_tombstones(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+) -> Result<impl Responder, AppError> {
+    require_admin(&state.db, user.user_id).await?;
 
-    // Check if email already exists
-    let exists = match &state.db {
+    let tombstones = match &state.db {
         DatabasePool::Postgres(pool) => {
-            let mut tx = pool.begin().await?;
-            let exists =
-                sqlx::query_as::<Postgres, (i32,)>("SELECT id FROM users WHERE email = $1")
-                    .bind(&payload.email)
-                    .fetch_optional(&mut *tx)
-                    .await?;
-            tx.commit().await?;
-            exists
+            sqlx::query_as::<_, CodeTombstone>(
+                r#"
+                SELECT * FROM code_tombstones
+                WHERE expires_at IS NULL OR expires_at > NOW()
+                ORDER BY released_at DESC
+                "#,
+            )
+            .fetch_all(pool)
+            .await?
         }
         DatabasePool::Sqlite(pool) => {
-            let mut tx = pool.begin().await?;
-            let exists = sqlx::query_as::<Sqlite, (i32,)>("SELECT id FROM users WHERE email = ?")
-                .bind(&payload.email)
-                .fetch_optional(&mut *tx)
-                .await?;
-            tx.commit().await?;
-            exists
+            sqlx::query_as::<_, CodeTombstone>(
+                r#"
+                SELECT * FROM code_tombstones
+                WHERE expires_at IS NULL OR datetime(expires_at) > datetime('now')
+                ORDER BY released_at DESC
+                "#,
+            )
+            .fetch_all(pool)
+            .await?
         }
     };
 
-    if exists.is_some() {
-        return Err(AppError::Auth("Email already registered".to_string()));
-    }
+    Ok(HttpResponse::Ok().json(tombstones))
+}
 
-    let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
-    let password_hash = argon2
-        .hash_password(payload.password.as_bytes(), &salt)
-        .map_err(|e| AppError::Auth(e.to_string()))?
-        .to_string();
+/// Releases a retired short code so that anyone can claim it again.
+pub async fn release_tombstone(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+) -> Result<impl Responder, AppError> {
+    require_admin(&state.db, user.user_id).await?;
+    let code = path.into_inner();
 
-    // Insert new user
-    let user = match &state.db {
+    let result = match &state.db {
         DatabasePool::Postgres(pool) => {
-            let mut tx = pool.begin().await?;
-            let user = sqlx::query_as::<Postgres, User>(
-                "INSERT INTO users (email, password_hash) VALUES ($1, $2) RETURNING *",
-            )
-            .bind(&payload.email)
-            .bind(&password_hash)
-            .fetch_one(&mut *tx)
-            .await?;
-            tx.commit().await?;
-            user
+            sqlx::query("DELETE FROM code_tombstones WHERE short_code = $1")
+                .bind(&code)
+                .execute(pool)
+                .await?
+                .rows_affected()
         }
         DatabasePool::Sqlite(pool) => {
-            let mut tx = pool.begin().await?;
-            let user = sqlx::query_as::<Sqlite, User>(
-                "INSERT INTO users (email, password_hash) VALUES (?, ?) RETURNING *",
-            )
-            .bind(&payload.email)
-            .bind(&password_hash)
-            .fetch_one(&mut *tx)
-            .await?;
-            tx.commit().await?;
-            user
+            sqlx::query("DELETE FROM code_tombstones WHERE short_code = ?1")
+                .bind(&code)
+                .execute(pool)
+                .await?
+                .rows_affected()
         }
     };
 
-    let claims = Claims::new(user.id);
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "default_secret".to_string());
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    )
-    .map_err(|e| AppError::Auth(e.to_string()))?;
-
-    Ok(HttpResponse::Ok().json(AuthResponse {
-        token,
-        user: UserResponse {
-            id: user.id,
-            email: user.email,
-        },
-    }))
+    if result == 0 {
+        Err(AppError::NotFound)
+    } else {
+        Ok(HttpResponse::NoContent().finish())
+    }
 }
 
-pub async fn login(
+pub async fn list_domain_rules(
     state: web::Data<AppState>,
-    payload: web::Json<LoginRequest>,
+    user: AuthenticatedUser,
 ) -> Result<impl Responder, AppError> {
-    let user = match &state.db {
+    require_admin(&state.db, user.user_id).await?;
+
+    let rules = match &state.db {
         DatabasePool::Postgres(pool) => {
-            let mut tx = pool.begin().await?;
-            let user = sqlx::query_as::<Postgres, User>("SELECT * FROM users WHERE email = $1")
-                .bind(&payload.email)
-                .fetch_optional(&mut *tx)
-                .await?;
-            tx.commit().await?;
-            user
+            sqlx::query_as::<_, DomainRule>("SELECT * FROM domain_rules ORDER BY id")
+                .fetch_all(pool)
+                .await?
         }
         DatabasePool::Sqlite(pool) => {
-            let mut tx = pool.begin().await?;
-            let user = sqlx::query_as::<Sqlite, User>("SELECT * FROM users WHERE email = ?")
-                .bind(&payload.email)
-                .fetch_optional(&mut *tx)
-                .await?;
-            tx.commit().await?;
-            user
+            sqlx::query_as::<_, DomainRule>("SELECT * FROM domain_rules ORDER BY id")
+                .fetch_all(pool)
+                .await?
         }
-    }
-    .ok_or_else(|| AppError::Auth("Invalid credentials".to_string()))?;
-
-    let argon2 = Argon2::default();
-    let parsed_hash =
-        PasswordHash::new(&user.password_hash).map_err(|e| AppError::Auth(e.to_string()))?;
-
-    if argon2
-        .verify_password(payload.password.as_bytes(), &parsed_hash)
-        .is_err()
-    {
-        return Err(AppError::Auth("Invalid credentials".to_string()));
-    }
-
-    let claims = Claims::new(user.id);
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "default_secret".to_string());
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    )
-    .map_err(|e| AppError::Auth(e.to_string()))?;
+    };
 
-    Ok(HttpResponse::Ok().json(AuthResponse {
-        token,
-        user: UserResponse {
-            id: user.id,
-            email: user.email,
-        },
-    }))
+    Ok(HttpResponse::Ok().json(rules))
 }
 
-pub async fn edit_link(
+/// Adds a domain rule. Existing links that break it are flagged in the
+/// background.
+pub async fn add_domain_rule(
     state: web::Data<AppState>,
     user: AuthenticatedUser,
-    path: web::Path<i32>,
-    payload: web::Json<CreateLink>,
+    payload: web::Json<DomainRuleRequest>,
 ) -> Result<impl Responder, AppError> {
-    let link_id: i32 = path.into_inner();
-
-    // Validate the new URL if provided
-    validate_url(&payload.url)?;
-
-    // Validate custom code if provided
-    if let Some(ref custom_code) = payload.custom_code {
-        validate_custom_code(custom_code)?;
+    require_admin(&state.db, user.user_id).await?;
+    let pattern = destinations::normalize_pattern(&payload.pattern)?;
 
-        // Check if the custom code is already taken by another link
-        let existing_link = match &state.db {
+    if let Some(user_id) = payload.user_id {
+        let exists = match &state.db {
             DatabasePool::Postgres(pool) => {
-                sqlx::query_as::<_, Link>("SELECT * FROM links WHERE short_code = $1 AND id != $2")
-                    .bind(custom_code)
-                    .bind(link_id)
+                sqlx::query_as::<_, (i32,)>("SELECT id FROM users WHERE id = $1")
+                    .bind(user_id)
                     .fetch_optional(pool)
                     .await?
             }
             DatabasePool::Sqlite(pool) => {
-                sqlx::query_as::<_, Link>("SELECT * FROM links WHERE short_code = ?1 AND id != ?2")
-                    .bind(custom_code)
-                    .bind(link_id)
+                sqlx::query_as::<_, (i32,)>("SELECT id FROM users WHERE id = ?1")
+                    .bind(user_id)
                     .fetch_optional(pool)
                     .await?
             }
         };
-
-        if existing_link.is_some() {
-            return Err(AppError::InvalidInput(
-                "Custom code already taken".to_string(),
-            ));
+        if exists.is_none() {
+            return Err(AppError::InvalidInput("User not found".to_string()));
         }
     }
 
-    // Update the link
-    let updated_link = match &state.db {
-        DatabasePool::Postgres(pool) => {
-            let mut tx = pool.begin().await?;
-
-            // First verify the link belongs to the user
-            let link =
-                sqlx::query_as::<_, Link>("SELECT * FROM links WHERE id = $1 AND user_id = $2")
-                    .bind(link_id)
-                    .bind(user.user_id)
-                    .fetch_optional(&mut *tx)
-                    .await?;
-
-            if link.is_none() {
-                return Err(AppError::NotFound);
-            }
+    let rule = match &state.db {
+        DatabasePool::Postgres(pool) => sqlx::query_as::<_, DomainRule>(
+            "INSERT INTO domain_rules (user_id, pattern, action) VALUES ($1, $2, $3) RETURNING *",
+        )
+        .bind(payload.user_id)
+        .bind(&pattern)
+        .bind(payload.action.as_str())
+        .fetch_one(pool)
+        .await?,
+        DatabasePool::Sqlite(pool) => sqlx::query_as::<_, DomainRule>(
+            "INSERT INTO domain_rules (user_id, pattern, action) VALUES (?1, ?2, ?3) RETURNING *",
+        )
+        .bind(payload.user_id)
+        .bind(&pattern)
+        .bind(payload.action.as_str())
+        .fetch_one(pool)
+        .await?,
+    };
 
-            // Update the link
-            let updated = sqlx::query_as::<_, Link>(
-                r#"
-                UPDATE links 
-                SET 
-                    original_url = $1,
-                    short_code = COALESCE($2, short_code)
-                WHERE id = $3 AND user_id = $4
-                RETURNING *
-                "#,
-            )
-            .bind(&payload.url)
-            .bind(&payload.custom_code)
-            .bind(link_id)
-            .bind(user.user_id)
-            .fetch_one(&mut *tx)
-            .await?;
+    state.destinations.spawn_reflag(&state.db, rule.user_id);
+    Ok(HttpResponse::Accepted().json(json!({ "rule": rule })))
+}
 
-            // If source is provided, add a click record
-            if let Some(ref source) = payload.source {
-                sqlx::query("INSERT INTO clicks (link_id, source) VALUES ($1, $2)")
-                    .bind(link_id)
-                    .bind(source)
-                    .execute(&mut *tx)
-                    .await?;
-            }
+/// Removes a domain rule. The flag of links it no longer blocks is cleared
+/// in the background.
+pub async fn remove_domain_rule(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<impl Responder, AppError> {
+    require_admin(&state.db, user.user_id).await?;
+    let rule_id = path.into_inner();
 
-            tx.commit().await?;
-            updated
+    let removed = match &state.db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query_as::<_, DomainRule>("DELETE FROM domain_rules WHERE id = $1 RETURNING *")
+                .bind(rule_id)
+                .fetch_optional(pool)
+                .await?
         }
         DatabasePool::Sqlite(pool) => {
-            let mut tx = pool.begin().await?;
-
-            // First verify the link belongs to the user
-            let link =
-                sqlx::query_as::<_, Link>("SELECT * FROM links WHERE id = ?1 AND user_id = ?2")
-                    .bind(link_id)
-                    .bind(user.user_id)
-                    .fetch_optional(&mut *tx)
-                    .await?;
-
-            if link.is_none() {
-                return Err(AppError::NotFound);
-            }
-
-            // Update the link
-            let updated = sqlx::query_as::<_, Link>(
-                r#"
-                UPDATE links 
-                SET 
-                    original_url = ?1,
-                    short_code = COALESCE(?2, short_code)
-                WHERE id = ?3 AND user_id = ?4
-                RETURNING *
-                "#,
-            )
-            .bind(&payload.url)
-            .bind(&payload.custom_code)
-            .bind(link_id)
-            .bind(user.user_id)
-            .fetch_one(&mut *tx)
-            .await?;
-
-            // If source is provided, add a click record
-            if let Some(ref source) = payload.source {
-                sqlx::query("INSERT INTO clicks (link_id, source) VALUES (?1, ?2)")
-                    .bind(link_id)
-                    .bind(source)
-                    .execute(&mut *tx)
-                    .await?;
-            }
-
-            tx.commit().await?;
-            updated
+            sqlx::query_as::<_, DomainRule>("DELETE FROM domain_rules WHERE id = ?1 RETURNING *")
+                .bind(rule_id)
+                .fetch_optional(pool)
+                .await?
         }
     };
+    let Some(rule) = removed else {
+        return Err(AppError::NotFound);
+    };
 
-    Ok(HttpResponse::Ok().json(updated_link))
+    state.destinations.spawn_reflag(&state.db, rule.user_id);
+    Ok(HttpResponse::Accepted().json(json!({ "rule": rule })))
 }
 
-pub async fn delete_link(
+/// Lists every user's links whose destination breaks a domain rule.
+pub async fn list_flagged_links(
     state: web::Data<AppState>,
     user: AuthenticatedUser,
-    path: web::Path<i32>,
 ) -> Result<impl Responder, AppError> {
-    let link_id: i32 = path.into_inner();
+    require_admin(&state.db, user.user_id).await?;
 
-    match &state.db {
+    let mut links = match &state.db {
         DatabasePool::Postgres(pool) => {
-            let mut tx = pool.begin().await?;
-
-            // Verify the link belongs to the user
-            let link = sqlx::query_as::<Postgres, (i32,)>(
-                "SELECT id FROM links WHERE id = $1 AND user_id = $2",
+            sqlx::query_as::<_, Link>(
+                r#"
+                SELECT * FROM links
+                WHERE flagged_at IS NOT NULL AND deleted_at IS NULL
+                ORDER BY flagged_at DESC
+                "#,
             )
-            .bind(link_id)
-            .bind(user.user_id)
-            .fetch_optional(&mut *tx)
-            .await?;
-
-            if link.is_none() {
-                return Err(AppError::NotFound);
-            }
-
-            // Delete associated clicks first due to foreign key constraint
-            sqlx::query("DELETE FROM clicks WHERE link_id = $1")
-                .bind(link_id)
-                .execute(&mut *tx)
-                .await?;
-
-            // Delete the link
-            sqlx::query("DELETE FROM links WHERE id = $1")
-                .bind(link_id)
-                .execute(&mut *tx)
-                .await?;
-
-            tx.commit().await?;
+            .fetch_all(pool)
+            .await?
         }
         DatabasePool::Sqlite(pool) => {
-            let mut tx = pool.begin().await?;
-
-            // Verify the link belongs to the user
-            let link = sqlx::query_as::<Sqlite, (i32,)>(
-                "SELECT id FROM links WHERE id = ? AND user_id = ?",
+            sqlx::query_as::<_, Link>(
+                r#"
+                SELECT * FROM links
+                WHERE flagged_at IS NOT NULL AND deleted_at IS NULL
+                ORDER BY flagged_at DESC
+                "#,
             )
-            .bind(link_id)
-            .bind(user.user_id)
-            .fetch_optional(&mut *tx)
-            .await?;
-
-            if link.is_none() {
-                return Err(AppError::NotFound);
-            }
-
-            // Delete associated clicks first due to foreign key constraint
-            sqlx::query("DELETE FROM clicks WHERE link_id = ?")
-                .bind(link_id)
-                .execute(&mut *tx)
-                .await?;
+            .fetch_all(pool)
+            .await?
+        }
+    };
+    attach_tags(&state.db, links.iter_mut().collect()).await?;
 
-            // Delete the link
-            sqlx::query("DELETE FROM links WHERE id = ?")
-                .bind(link_id)
-                .execute(&mut *tx)
-                .await?;
+    Ok(HttpResponse::Ok().json(links))
+}
 
-            tx.commit().await?;
-        }
-    }
+const MAX_REPORT_REASON_LEN: usize = 1000;
 
-    Ok(HttpResponse::NoContent().finish())
+/// Hands out a proof-of-work challenge for `report_link`.
+pub async fn get_report_challenge(state: web::Data<AppState>) -> Result<impl Responder, AppError> {
+    let challenge = state.reports.challenge();
+    Ok(HttpResponse::Ok().json(ReportChallenge {
+        challenge,
+        difficulty: state.reports.difficulty(),
+        expires_in: reports::CHALLENGE_TTL.as_secs(),
+    }))
 }
 
-pub async fn get_link_clicks(
+/// Files an abuse report for a short link. Doesn't need an account.
+pub async fn report_link(
     state: web::Data<AppState>,
-    user: AuthenticatedUser,
-    path: web::Path<i32>,
+    req: HttpRequest,
+    payload: web::Json<ReportRequest>,
 ) -> Result<impl Responder, AppError> {
-    let link_id = path.into_inner();
+    let reason = normalize_text("Reason", Some(&payload.reason), MAX_REPORT_REASON_LEN)?
+        .ok_or_else(|| AppError::InvalidInput("Reason cannot be empty".to_string()))?;
+    // Only reports with a solved challenge count against the limit, so
+    // requests that cost nothing don't take up any memory
+    state.reports.redeem(&payload.challenge, &payload.nonce)?;
+    let client = state.reports.client(&req);
+    state.reports.check_rate(&client)?;
 
-    // First verify the link belongs to the user
-    let link = match &state.db {
+    let report_id = match &state.db {
         DatabasePool::Postgres(pool) => {
-            sqlx::query_as::<_, (i32,)>("SELECT id FROM links WHERE id = $1 AND user_id = $2")
-                .bind(link_id)
-                .bind(user.user_id)
-                .fetch_optional(pool)
-                .await?
+            sqlx::query_as::<_, (i32,)>(
+                r#"
+                INSERT INTO abuse_reports (link_id, short_code, reason, reporter_ip)
+                SELECT links.id, $1, $2, $3 FROM link_codes
+                JOIN links ON links.id = link_codes.link_id
+                WHERE link_codes.short_code = $1 AND links.deleted_at IS NULL
+                RETURNING id
+                "#,
+            )
+            .bind(&payload.short_code)
+            .bind(&reason)
+            .bind(&client)
+            .fetch_optional(pool)
+            .await?
         }
         DatabasePool::Sqlite(pool) => {
-            sqlx::query_as::<_, (i32,)>("SELECT id FROM links WHERE id = ? AND user_id = ?")
-                .bind(link_id)
-                .bind(user.user_id)
-                .fetch_optional(pool)
-                .await?
+            sqlx::query_as::<_, (i32,)>(
+                r#"
+                INSERT INTO abuse_reports (link_id, short_code, reason, reporter_ip)
+                SELECT links.id, ?1, ?2, ?3 FROM link_codes
+                JOIN links ON links.id = link_codes.link_id
+                WHERE link_codes.short_code = ?1 AND links.deleted_at IS NULL
+                RETURNING id
+                "#,
+            )
+            .bind(&payload.short_code)
+            .bind(&reason)
+            .bind(&client)
+            .fetch_optional(pool)
+            .await?
         }
     };
-
-    if link.is_none() {
+    let Some((report_id,)) = report_id else {
         return Err(AppError::NotFound);
-    }
+    };
 
-    let clicks = match &state.db {
+    tracing::info!(
+        "Received abuse report {} for {}",
+        report_id,
+        payload.short_code
+    );
+    Ok(HttpResponse::Accepted().json(json!({ "id": report_id })))
+}
+
+/// The moderation queue: abuse reports, newest first.
+pub async fn list_reports(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    query: web::Query<ReportListQuery>,
+) -> Result<impl Responder, AppError> {
+    require_admin(&state.db, user.user_id).await?;
+    let status = match query.status {
+        ReportStatusFilter::Open => Some("open"),
+        ReportStatusFilter::Dismissed => Some("dismissed"),
+        ReportStatusFilter::Actioned => Some("actioned"),
+        ReportStatusFilter::All => None,
+    };
+
+    let reports = match &state.db {
         DatabasePool::Postgres(pool) => {
-            sqlx::query_as::<_, ClickStats>(
+            sqlx::query_as::<_, AbuseReport>(
                 r#"
-                SELECT 
-                    DATE(created_at)::text as date,
-                    COUNT(*)::bigint as clicks
-                FROM clicks
-                WHERE link_id = $1
-                GROUP BY DATE(created_at)
-                ORDER BY DATE(created_at) ASC
+                SELECT abuse_reports.*, links.original_url, links.user_id AS owner_id
+                FROM abuse_reports
+                JOIN links ON links.id = abuse_reports.link_id
+                WHERE $1::TEXT IS NULL OR abuse_reports.status = $1
+                ORDER BY abuse_reports.created_at DESC, abuse_reports.id DESC
                 "#,
             )
-            .bind(link_id)
+            .bind(status)
             .fetch_all(pool)
             .await?
         }
         DatabasePool::Sqlite(pool) => {
-            sqlx::query_as::<_, ClickStats>(
+            sqlx::query_as::<_, AbuseReport>(
                 r#"
-                SELECT 
-                    DATE(created_at) as date,
-                    COUNT(*) as clicks
-                FROM clicks
-                WHERE link_id = ?
-                GROUP BY DATE(created_at)
-                ORDER BY DATE(created_at) ASC
+                SELECT abuse_reports.*, links.original_url, links.user_id AS owner_id
+                FROM abuse_reports
+                JOIN links ON links.id = abuse_reports.link_id
+                WHERE ?1 IS NULL OR abuse_reports.status = ?1
+                ORDER BY abuse_reports.created_at DESC, abuse_reports.id DESC
                 "#,
             )
-            .bind(link_id)
+            .bind(status)
             .fetch_all(pool)
             .await?
         }
     };
 
-    Ok(HttpResponse::Ok().json(clicks))
+    Ok(HttpResponse::Ok().json(reports))
 }
 
-pub async fn get_link_sources(
+/// Closes an open report without acting on it.
+pub async fn dismiss_report(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<impl Responder, AppError> {
+    require_admin(&state.db, user.user_id).await?;
+    let report_id = path.into_inner();
+
+    let result = match &state.db {
+        DatabasePool::Postgres(pool) => sqlx::query(
+            r#"
+                UPDATE abuse_reports
+                SET status = 'dismissed', resolved_at = NOW(), resolved_by = $2
+                WHERE id = $1 AND status = 'open'
+                "#,
+        )
+        .bind(report_id)
+        .bind(user.user_id)
+        .execute(pool)
+        .await?
+        .rows_affected(),
+        DatabasePool::Sqlite(pool) => sqlx::query(
+            r#"
+                UPDATE abuse_reports
+                SET status = 'dismissed', resolved_at = CURRENT_TIMESTAMP, resolved_by = ?2
+                WHERE id = ?1 AND status = 'open'
+                "#,
+        )
+        .bind(report_id)
+        .bind(user.user_id)
+        .execute(pool)
+        .await?
+        .rows_affected(),
+    };
+
+    if result == 0 {
+        Err(AppError::NotFound)
+    } else {
+        Ok(HttpResponse::NoContent().finish())
+    }
+}
+
+/// Disables any user's link and closes the open reports about it.
+pub async fn admin_disable_link(
     state: web::Data<AppState>,
     user: AuthenticatedUser,
     path: web::Path<i32>,
+    payload: Option<web::Json<ModerationRequest>>,
 ) -> Result<impl Responder, AppError> {
+    require_admin(&state.db, user.user_id).await?;
     let link_id = path.into_inner();
+    let reason = moderation_reason(payload, "Disabled by an admin")?;
 
-    // Verify the link belongs to the user
-    let link = match &state.db {
+    let updated = match &state.db {
         DatabasePool::Postgres(pool) => {
             let mut tx = pool.begin().await?;
-            let link = sqlx::query_as::<Postgres, (i32,)>(
-                "SELECT id FROM links WHERE id = $1 AND user_id = $2",
+            let updated = sqlx::query(
+                r#"
+                UPDATE links
+                SET enabled = FALSE, disabled_reason = $2, disabled_at = NOW(), disabled_by = $3,
+                    version = version + 1
+                WHERE id = $1 AND deleted_at IS NULL
+                "#,
             )
             .bind(link_id)
+            .bind(&reason)
             .bind(user.user_id)
-            .fetch_optional(&mut *tx)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+            sqlx::query(
+                r#"
+                UPDATE abuse_reports
+                SET status = 'actioned', resolved_at = NOW(), resolved_by = $2
+                WHERE link_id = $1 AND status = 'open'
+                "#,
+            )
+            .bind(link_id)
+            .bind(user.user_id)
+            .execute(&mut *tx)
             .await?;
             tx.commit().await?;
-            link
+            updated
         }
         DatabasePool::Sqlite(pool) => {
             let mut tx = pool.begin().await?;
-            let link = sqlx::query_as::<Sqlite, (i32,)>(
-                "SELECT id FROM links WHERE id = ? AND user_id = ?",
+            let updated = sqlx::query(
+                r#"
+                UPDATE links
+                SET enabled = FALSE, disabled_reason = ?2, disabled_at = CURRENT_TIMESTAMP,
+                    disabled_by = ?3, version = version + 1
+                WHERE id = ?1 AND deleted_at IS NULL
+                "#,
             )
             .bind(link_id)
+            .bind(&reason)
             .bind(user.user_id)
-            .fetch_optional(&mut *tx)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+            sqlx::query(
+                r#"
+                UPDATE abuse_reports
+                SET status = 'actioned', resolved_at = CURRENT_TIMESTAMP, resolved_by = ?2
+                WHERE link_id = ?1 AND status = 'open'
+                "#,
+            )
+            .bind(link_id)
+            .bind(user.user_id)
+            .execute(&mut *tx)
             .await?;
             tx.commit().await?;
-            link
+            updated
         }
     };
 
-    if link.is_none() {
-        return Err(AppError::NotFound);
+    if updated == 0 {
+        Err(AppError::NotFound)
+    } else {
+        Ok(HttpResponse::NoContent().finish())
     }
+}
+
+/// Bans a user: their tokens stop working, they can't sign in, all their
+/// links are disabled and open reports about them are closed.
+pub async fn ban_user(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+    payload: Option<web::Json<ModerationRequest>>,
+) -> Result<impl Responder, AppError> {
+    require_admin(&state.db, user.user_id).await?;
+    let target = path.into_inner();
+    let reason = moderation_reason(payload, "Banned by an admin")?;
 
-    let sources = match &state.db {
+    let target_admin = match &state.db {
         DatabasePool::Postgres(pool) => {
-            sqlx::query_as::<_, SourceStats>(
+            sqlx::query_as::<_, (bool,)>("SELECT is_admin FROM users WHERE id = $1")
+                .bind(target)
+                .fetch_optional(pool)
+                .await?
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query_as::<_, (bool,)>("SELECT is_admin FROM users WHERE id = ?1")
+                .bind(target)
+                .fetch_optional(pool)
+                .await?
+        }
+    };
+    match target_admin {
+        None => return Err(AppError::NotFound),
+        Some((true,)) => return Err(AppError::InvalidInput("Admins can't be banned".to_string())),
+        Some((false,)) => {}
+    }
+
+    match &state.db {
+        DatabasePool::Postgres(pool) => {
+            let mut tx = pool.begin().await?;
+            sqlx::query("UPDATE users SET banned_at = NOW(), ban_reason = $2 WHERE id = $1")
+                .bind(target)
+                .bind(&reason)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query(
                 r#"
-                SELECT 
-                    DATE(created_at)::text as date,
-                    query_source as source,
-                    COUNT(*)::bigint as count
-                FROM clicks
-                WHERE link_id = $1
-                    AND query_source IS NOT NULL
-                    AND query_source != ''
-                GROUP BY DATE(created_at), query_source
-                ORDER BY DATE(created_at) ASC, COUNT(*) DESC
+                UPDATE links
+                SET enabled = FALSE, disabled_reason = 'The owner was banned',
+                    disabled_at = NOW(), disabled_by = $2, version = version + 1
+                WHERE user_id = $1 AND enabled
                 "#,
             )
-            .bind(link_id)
-            .fetch_all(pool)
-            .await?
+            .bind(target)
+            .bind(user.user_id)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(
+                r#"
+                UPDATE abuse_reports
+                SET status = 'actioned', resolved_at = NOW(), resolved_by = $2
+                WHERE status = 'open'
+                    AND link_id IN (SELECT id FROM links WHERE user_id = $1)
+                "#,
+            )
+            .bind(target)
+            .bind(user.user_id)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
         }
         DatabasePool::Sqlite(pool) => {
-            sqlx::query_as::<_, SourceStats>(
+            let mut tx = pool.begin().await?;
+            sqlx::query(
+                "UPDATE users SET banned_at = CURRENT_TIMESTAMP, ban_reason = ?2 WHERE id = ?1",
+            )
+            .bind(target)
+            .bind(&reason)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(
                 r#"
-                SELECT 
-                    DATE(created_at) as date,
-                    query_source as source,
-                    COUNT(*) as count
-                FROM clicks
-                WHERE link_id = ?
-                    AND query_source IS NOT NULL
-                    AND query_source != ''
-                GROUP BY DATE(created_at), query_source
-                ORDER BY DATE(created_at) ASC, COUNT(*) DESC
+                UPDATE links
+                SET enabled = FALSE, disabled_reason = 'The owner was banned',
+                    disabled_at = CURRENT_TIMESTAMP, disabled_by = ?2, version = version + 1
+                WHERE user_id = ?1 AND enabled
                 "#,
             )
-            .bind(link_id)
-            .fetch_all(pool)
-            .await?
+            .bind(target)
+            .bind(user.user_id)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(
+                r#"
+                UPDATE abuse_reports
+                SET status = 'actioned', resolved_at = CURRENT_TIMESTAMP, resolved_by = ?2
+                WHERE status = 'open'
+                    AND link_id IN (SELECT id FROM links WHERE user_id = ?1)
+                "#,
+            )
+            .bind(target)
+            .bind(user.user_id)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
         }
+    }
+    state.banned.insert(target);
+
+    tracing::info!("User {} was banned by {}", target, user.user_id);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Lifts a ban. The user's links stay disabled.
+pub async fn unban_user(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<impl Responder, AppError> {
+    require_admin(&state.db, user.user_id).await?;
+    let target = path.into_inner();
+
+    let result = match &state.db {
+        DatabasePool::Postgres(pool) => sqlx::query(
+            r#"
+                UPDATE users SET banned_at = NULL, ban_reason = NULL
+                WHERE id = $1 AND banned_at IS NOT NULL
+                "#,
+        )
+        .bind(target)
+        .execute(pool)
+        .await?
+        .rows_affected(),
+        DatabasePool::Sqlite(pool) => sqlx::query(
+            r#"
+                UPDATE users SET banned_at = NULL, ban_reason = NULL
+                WHERE id = ?1 AND banned_at IS NOT NULL
+                "#,
+        )
+        .bind(target)
+        .execute(pool)
+        .await?
+        .rows_affected(),
     };
 
-    Ok(HttpResponse::Ok().json(sources))
+    if result == 0 {
+        return Err(AppError::NotFound);
+    }
+    state.banned.remove(target);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+fn moderation_reason(
+    payload: Option<web::Json<ModerationRequest>>,
+    default: &str,
+) -> Result<String, AppError> {
+    let reason = payload.and_then(|payload| payload.into_inner().reason);
+    Ok(
+        normalize_text("Reason", reason.as_deref(), MAX_REPORT_REASON_LEN)?
+            .unwrap_or_else(|| default.to_string()),
+    )
 }
 
 pub async fn check_first_user(state: web::Data<AppState>) -> Result<impl Responder, AppError> {
@@ -0,0 +1,384 @@
+//! Parsers that turn export files into `ExportedLink` records for the import
+//! endpoint. Besides SimpleLink's own JSON and CSV exports this understands
+//! dumps from YOURLS, Shlink and Bitly, and Netscape bookmark files.
+
+use crate::error::AppError;
+use crate::models::{ExportedLink, ImportFormat, LinkExport};
+use crate::text::{decode_entities, html_attributes};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// The newest `LinkExport::version` this build can read.
+pub const EXPORT_VERSION: u32 = 1;
+
+/// One parsed record, or the reason it could not be read.
+pub type ImportRecord = Result<ExportedLink, String>;
+
+lazy_static! {
+    static ref SQL_INSERT_REGEX: Regex =
+        Regex::new(r"(?is)INSERT\s+INTO\s+[`\x22]?(\w+)[`\x22]?\s*(?:\(([^)]*)\))?\s*VALUES\s*")
+            .unwrap();
+    static ref BOOKMARK_REGEX: Regex = Regex::new(r"(?is)<a\s([^>]*)>(.*?)</a>").unwrap();
+    static ref TAG_REGEX: Regex = Regex::new(r"<[^>]*>").unwrap();
+}
+
+pub fn parse(format: ImportFormat, body: &[u8]) -> Result<Vec<ImportRecord>, AppError> {
+    match format {
+        ImportFormat::Json => parse_simplelink_json(body),
+        ImportFormat::Csv => parse_simplelink_csv(body),
+        ImportFormat::YourlsSql => parse_yourls_sql(&text(body)?),
+        ImportFormat::YourlsCsv => parse_csv_with_columns(body, &YOURLS_COLUMNS),
+        ImportFormat::ShlinkCsv => parse_csv_with_columns(body, &SHLINK_COLUMNS),
+        ImportFormat::BitlyCsv => parse_csv_with_columns(body, &BITLY_COLUMNS),
+        ImportFormat::NetscapeHtml => Ok(parse_netscape_bookmarks(&text(body)?)),
+    }
+}
+
+fn text(body: &[u8]) -> Result<String, AppError> {
+    String::from_utf8(body.to_vec())
+        .map_err(|_| AppError::InvalidInput("Import file must be UTF-8 encoded".to_string()))
+}
+
+fn parse_simplelink_json(body: &[u8]) -> Result<Vec<ImportRecord>, AppError> {
+    let export: LinkExport = serde_json::from_slice(body)
+        .map_err(|e| AppError::InvalidInput(format!("Invalid export file: {}", e)))?;
+    if export.version > EXPORT_VERSION {
+        return Err(AppError::InvalidInput(format!(
+            "Unsupported export version {}",
+            export.version
+        )));
+    }
+    Ok(export.links.into_iter().map(Ok).collect())
+}
+
+fn parse_simplelink_csv(body: &[u8]) -> Result<Vec<ImportRecord>, AppError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body);
+    Ok(reader
+        .deserialize::<ExportedLink>()
+        .map(|row| row.map_err(|e| format!("Invalid CSV row: {}", e)))
+        .collect())
+}
+
+/// Header names (normalised to lowercase alphanumerics) that may hold each
+/// field in another shortener's CSV export, in order of preference.
+struct CsvColumns {
+    code: &'static [&'static str],
+    url: &'static [&'static str],
+    created_at: &'static [&'static str],
+    clicks: &'static [&'static str],
+    title: &'static [&'static str],
+}
+
+const YOURLS_COLUMNS: CsvColumns = CsvColumns {
+    code: &["keyword", "shorturl"],
+    url: &["url", "longurl"],
+    created_at: &["timestamp", "date"],
+    clicks: &["clicks"],
+    title: &["title"],
+};
+
+const SHLINK_COLUMNS: CsvColumns = CsvColumns {
+    code: &["shortcode", "shorturl"],
+    url: &["longurl", "originalurl"],
+    created_at: &["createdat", "datecreated"],
+    clicks: &["visits", "visitscount", "visitstotal"],
+    title: &["title"],
+};
+
+const BITLY_COLUMNS: CsvColumns = CsvColumns {
+    code: &["bitlink", "link", "shortlink", "shorturl"],
+    url: &["longurl", "originalurl", "destination", "url"],
+    created_at: &["created", "datecreated", "createdat", "date"],
+    clicks: &["clicks", "totalclicks", "engagements"],
+    title: &["title"],
+};
+
+fn normalise_header(header: &str) -> String {
+    header
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn parse_csv_with_columns(
+    body: &[u8],
+    columns: &CsvColumns,
+) -> Result<Vec<ImportRecord>, AppError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(body);
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| AppError::InvalidInput(format!("Invalid CSV header: {}", e)))?
+        .iter()
+        .map(normalise_header)
+        .collect();
+    let find = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| headers.iter().position(|h| h == name))
+    };
+
+    let (Some(code_col), Some(url_col)) = (find(columns.code), find(columns.url)) else {
+        return Err(AppError::InvalidInput(
+            "CSV is missing a short code or destination URL column".to_string(),
+        ));
+    };
+    let created_col = find(columns.created_at);
+    let clicks_col = find(columns.clicks);
+    let title_col = find(columns.title);
+
+    Ok(reader
+        .records()
+        .map(|row| {
+            let row = row.map_err(|e| format!("Invalid CSV row: {}", e))?;
+            let field = |col: Option<usize>| col.and_then(|c| row.get(c)).unwrap_or("");
+            Ok(ExportedLink {
+                short_code: code_from_short_url(field(Some(code_col))),
+                original_url: field(Some(url_col)).to_string(),
+                created_at: parse_timestamp(field(created_col)),
+                clicks: parse_clicks(field(clicks_col)),
+                title: non_empty(field(title_col)),
+                description: None,
+                notes: None,
+            })
+        })
+        .collect())
+}
+
+/// Short code columns sometimes hold the whole short URL (`https://bit.ly/abc`)
+/// rather than just the code.
+fn code_from_short_url(value: &str) -> String {
+    value
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or("")
+        .to_string()
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+fn parse_clicks(value: &str) -> i64 {
+    value.replace(',', "").trim().parse().unwrap_or(0)
+}
+
+/// Accepts the timestamp styles the supported exports use: RFC 3339, MySQL
+/// `DATETIME`, bare dates, Bitly's `+0000 UTC` suffix and Unix seconds.
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim().trim_end_matches(" UTC");
+    if value.is_empty() {
+        return None;
+    }
+    if let Ok(ts) = DateTime::parse_from_rfc3339(value) {
+        return Some(ts.with_timezone(&Utc));
+    }
+    if let Ok(ts) = DateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S %z") {
+        return Some(ts.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"] {
+        if let Ok(ts) = NaiveDateTime::parse_from_str(value, format) {
+            return Some(Utc.from_utc_datetime(&ts));
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return date
+            .and_hms_opt(0, 0, 0)
+            .map(|ts| Utc.from_utc_datetime(&ts));
+    }
+    value
+        .parse::<i64>()
+        .ok()
+        .and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+}
+
+/// Reads the `yourls_url` rows out of a mysqldump of a YOURLS database.
+/// Inserts into other tables (options, logs) are ignored.
+fn parse_yourls_sql(sql: &str) -> Result<Vec<ImportRecord>, AppError> {
+    // Column order of the yourls_url table, used when a dump omits the list
+    const DEFAULT_COLUMNS: [&str; 6] = ["keyword", "url", "title", "timestamp", "ip", "clicks"];
+
+    let mut records = Vec::new();
+    let mut found_table = false;
+
+    for captures in SQL_INSERT_REGEX.captures_iter(sql) {
+        let table = captures.get(1).map_or("", |m| m.as_str()).to_lowercase();
+        if table != "url" && !table.ends_with("_url") {
+            continue;
+        }
+        found_table = true;
+
+        let columns: Vec<String> = match captures.get(2) {
+            Some(list) => list
+                .as_str()
+                .split(',')
+                .map(|c| c.trim().trim_matches(['`', '"']).to_lowercase())
+                .collect(),
+            None => DEFAULT_COLUMNS.iter().map(|c| c.to_string()).collect(),
+        };
+        let position = |name: &str| columns.iter().position(|c| c == name);
+        let (Some(code_col), Some(url_col)) = (position("keyword"), position("url")) else {
+            continue;
+        };
+        let created_col = position("timestamp");
+        let clicks_col = position("clicks");
+        let title_col = position("title");
+
+        let values_start = captures.get(0).map_or(0, |m| m.end());
+        for row in parse_sql_tuples(&sql[values_start..]) {
+            let record = match row {
+                Ok(values) => {
+                    let field = |col: Option<usize>| {
+                        col.and_then(|c| values.get(c))
+                            .and_then(|v| v.as_deref())
+                            .unwrap_or("")
+                    };
+                    match (
+                        values.get(code_col).cloned().flatten(),
+                        values.get(url_col).cloned().flatten(),
+                    ) {
+                        (Some(code), Some(url)) => Ok(ExportedLink {
+                            short_code: code,
+                            original_url: url,
+                            created_at: parse_timestamp(field(created_col)),
+                            clicks: parse_clicks(field(clicks_col)),
+                            title: non_empty(field(title_col)),
+                            description: None,
+                            notes: None,
+                        }),
+                        _ => Err("Row is missing its keyword or URL".to_string()),
+                    }
+                }
+                Err(message) => Err(message),
+            };
+            records.push(record);
+        }
+    }
+
+    if !found_table {
+        return Err(AppError::InvalidInput(
+            "No INSERT statements for the YOURLS url table were found".to_string(),
+        ));
+    }
+    Ok(records)
+}
+
+/// Parses the `(...), (...);` tuple list that follows `VALUES`, stopping at
+/// the terminating semicolon. `NULL` becomes `None`.
+fn parse_sql_tuples(input: &str) -> Vec<Result<Vec<Option<String>>, String>> {
+    let mut rows = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace() || *c == ',') {
+            chars.next();
+        }
+        match chars.next() {
+            Some('(') => {}
+            _ => break,
+        }
+
+        let mut values = Vec::new();
+        let mut current = String::new();
+        let mut quoted = false;
+        let mut was_quoted = false;
+        let mut closed = false;
+
+        while let Some(c) = chars.next() {
+            if quoted {
+                match c {
+                    '\\' => {
+                        if let Some(escaped) = chars.next() {
+                            current.push(match escaped {
+                                'n' => '\n',
+                                'r' => '\r',
+                                't' => '\t',
+                                '0' => '\0',
+                                other => other,
+                            });
+                        }
+                    }
+                    '\'' if chars.peek() == Some(&'\'') => {
+                        chars.next();
+                        current.push('\'');
+                    }
+                    '\'' => quoted = false,
+                    other => current.push(other),
+                }
+                continue;
+            }
+            match c {
+                '\'' => {
+                    quoted = true;
+                    was_quoted = true;
+                }
+                ',' | ')' => {
+                    let value = if was_quoted {
+                        Some(std::mem::take(&mut current))
+                    } else {
+                        let raw = std::mem::take(&mut current);
+                        let raw = raw.trim();
+                        (!raw.eq_ignore_ascii_case("null")).then(|| raw.to_string())
+                    };
+                    values.push(value);
+                    was_quoted = false;
+                    if c == ')' {
+                        closed = true;
+                        break;
+                    }
+                }
+                other => current.push(other),
+            }
+        }
+
+        if !closed {
+            rows.push(Err("Unterminated row in SQL dump".to_string()));
+            break;
+        }
+        rows.push(Ok(values));
+    }
+
+    rows
+}
+
+/// Reads a Netscape bookmark file as exported by browsers. A bookmark's
+/// keyword (`SHORTCUTURL`) becomes its short code; bookmarks without one get
+/// a generated code on import. The link text becomes the title.
+fn parse_netscape_bookmarks(html: &str) -> Vec<ImportRecord> {
+    BOOKMARK_REGEX
+        .captures_iter(html)
+        .filter_map(|captures| {
+            let mut href = None;
+            let mut keyword = None;
+            let mut added = None;
+            for (name, value) in html_attributes(captures.get(1)?.as_str()) {
+                match name.as_str() {
+                    "href" => href = Some(value),
+                    "shortcuturl" => keyword = Some(value),
+                    "add_date" => added = parse_timestamp(&value),
+                    _ => {}
+                }
+            }
+            // Skip bookmarklets and other non-web entries
+            let href = href.filter(|h| h.starts_with("http://") || h.starts_with("https://"))?;
+            let text = TAG_REGEX.replace_all(captures.get(2).map_or("", |m| m.as_str()), "");
+            Some(Ok(ExportedLink {
+                short_code: keyword.unwrap_or_default(),
+                original_url: href,
+                created_at: added,
+                clicks: 0,
+                title: non_empty(&decode_entities(&text)),
+                description: None,
+                notes: None,
+            }))
+        })
+        .collect()
+}
@@ -10,14 +10,40 @@ use tracing::info;
 use models::DatabasePool;
 
 pub mod auth;
+pub mod blocklists;
+pub mod codes;
+pub mod destinations;
+pub mod embeds;
 pub mod error;
 pub mod handlers;
+pub mod importers;
+pub mod metadata;
 pub mod models;
+pub mod pages;
+pub mod qr;
+pub mod reports;
+pub mod text;
+pub mod trash;
+
+/// The frontend build, along with templates such as the link preview page
+#[derive(rust_embed::RustEmbed)]
+#[folder = "static/"]
+pub struct Asset;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: DatabasePool,
     pub admin_token: Option<String>,
+    pub metadata: metadata::MetadataFetcher,
+    pub trash: trash::TrashConfig,
+    pub codes: codes::CodeGenerator,
+    pub destinations: destinations::DestinationPolicy,
+    pub reports: reports::ReportGuard,
+    pub banned: auth::BannedUsers,
+    pub disabled_page: pages::DisabledPage,
+    pub qr_logo: qr::QrLogo,
+    /// Whether `create_short_url` reuses existing links by default
+    pub dedupe_links: bool,
 }
 
 pub async fn create_db_pool() -> Result<DatabasePool> {
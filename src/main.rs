@@ -1,21 +1,21 @@
 use actix_cors::Cors;
 use actix_web::{web, App, HttpResponse, HttpServer};
 use anyhow::Result;
-use clap::Parser;
-use rust_embed::RustEmbed;
+use simplelink::auth::BannedUsers;
 use simplelink::check_and_generate_admin_token;
+use simplelink::codes::CodeGenerator;
+use simplelink::destinations::DestinationPolicy;
+use simplelink::metadata::MetadataFetcher;
 use simplelink::models::DatabasePool;
+use simplelink::pages::DisabledPage;
+use simplelink::qr::QrLogo;
+use simplelink::reports::ReportGuard;
+use simplelink::trash::TrashConfig;
+use simplelink::{blocklists, handlers, trash, AppState, Asset};
 use simplelink::{create_db_pool, run_migrations};
-use simplelink::{handlers, AppState};
 use sqlx::{Postgres, Sqlite};
 use tracing::{error, info};
 
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-#[derive(RustEmbed)]
-#[folder = "static/"]
-struct Asset;
-
 async fn serve_static_file(path: &str) -> HttpResponse {
     match Asset::get(path) {
         Some(content) => {
@@ -50,6 +50,14 @@ async fn create_initial_links(pool: &DatabasePool) -> Result<()> {
                         .bind(1)
                         .execute(pool)
                         .await?;
+                        sqlx::query(
+                            "INSERT INTO link_codes (short_code, link_id)
+                             SELECT short_code, id FROM links WHERE short_code = $1
+                             ON CONFLICT (short_code) DO NOTHING",
+                        )
+                        .bind(code)
+                        .execute(pool)
+                        .await?;
                     }
                     DatabasePool::Sqlite(pool) => {
                         // First check if the exact combination exists
@@ -76,6 +84,13 @@ async fn create_initial_links(pool: &DatabasePool) -> Result<()> {
                             .bind(1)
                             .execute(pool)
                             .await?;
+                            sqlx::query(
+                                "INSERT INTO link_codes (short_code, link_id)
+                                 SELECT short_code, id FROM links WHERE short_code = ?1",
+                            )
+                            .bind(code)
+                            .execute(pool)
+                            .await?;
                             info!("Created initial link: {} -> {} for user_id: 1", code, url);
                         } else {
                             info!("Skipped existing link: {} -> {} for user_id: 1", code, url);
@@ -104,8 +119,8 @@ async fn create_admin_user(pool: &DatabasePool, email: &str, password: &str) ->
     match pool {
         DatabasePool::Postgres(pool) => {
             sqlx::query(
-                "INSERT INTO users (email, password_hash) 
-                 VALUES ($1, $2) 
+                "INSERT INTO users (email, password_hash, is_admin) 
+                 VALUES ($1, $2, TRUE) 
                  ON CONFLICT (email) DO NOTHING",
             )
             .bind(email)
@@ -115,8 +130,8 @@ async fn create_admin_user(pool: &DatabasePool, email: &str, password: &str) ->
         }
         DatabasePool::Sqlite(pool) => {
             sqlx::query(
-                "INSERT OR IGNORE INTO users (email, password_hash) 
-                 VALUES (?1, ?2)",
+                "INSERT OR IGNORE INTO users (email, password_hash, is_admin) 
+                 VALUES (?1, ?2, TRUE)",
             )
             .bind(email)
             .bind(&password_hash)
@@ -194,9 +209,26 @@ async fn main() -> Result<()> {
 
     let admin_token = check_and_generate_admin_token(&pool).await?;
 
+    let metadata = MetadataFetcher::from_env(pool.clone())?;
+    let codes = CodeGenerator::from_env()?;
+    let trash = TrashConfig::from_env();
+    trash::spawn_purge_job(pool.clone(), trash);
+    let destinations = DestinationPolicy::from_env()?;
+    blocklists::spawn_recheck_job(pool.clone(), destinations.clone());
+    let banned = BannedUsers::load(&pool).await?;
+
     let state = AppState {
         db: pool,
         admin_token,
+        metadata,
+        trash,
+        codes,
+        destinations,
+        reports: ReportGuard::from_env()?,
+        banned,
+        disabled_page: DisabledPage::from_env()?,
+        qr_logo: QrLogo::from_env()?,
+        dedupe_links: std::env::var("DEDUPE_LINKS").is_ok_and(|v| v == "true"),
     };
 
     let host = std::env::var("SERVER_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
@@ -209,6 +241,7 @@ async fn main() -> Result<()> {
             .allow_any_origin()
             .allow_any_method()
             .allow_any_header()
+            .expose_headers(["ETag"])
             .max_age(3600);
 
         App::new()
@@ -218,24 +251,140 @@ async fn main() -> Result<()> {
                 web::scope("/api")
                     .route("/shorten", web::post().to(handlers::create_short_url))
                     .route("/links", web::get().to(handlers::get_all_links))
+                    .route("/links/search", web::get().to(handlers::search_links))
+                    .route("/links/export", web::get().to(handlers::export_links))
+                    .route("/links/trash", web::get().to(handlers::list_trash))
+                    .service(
+                        web::resource("/links/import")
+                            .app_data(web::PayloadConfig::new(16 * 1024 * 1024))
+                            .route(web::post().to(handlers::import_links)),
+                    )
+                    .service(
+                        web::resource("/links/bulk")
+                            .app_data(web::PayloadConfig::new(4 * 1024 * 1024))
+                            .route(web::post().to(handlers::bulk_create_links)),
+                    )
+                    .route("/links/{id}", web::get().to(handlers::get_link))
                     .route("/links/{id}", web::delete().to(handlers::delete_link))
+                    .route(
+                        "/links/{id}/restore",
+                        web::post().to(handlers::restore_link),
+                    )
                     .route(
                         "/links/{id}/clicks",
                         web::get().to(handlers::get_link_clicks),
                     )
+                    .route(
+                        "/links/{id}/aliases",
+                        web::get().to(handlers::list_link_codes),
+                    )
+                    .route(
+                        "/links/{id}/aliases",
+                        web::post().to(handlers::add_link_alias),
+                    )
+                    .route(
+                        "/links/{id}/aliases/{code}",
+                        web::delete().to(handlers::remove_link_alias),
+                    )
+                    .route(
+                        "/links/{id}/history",
+                        web::get().to(handlers::get_link_history),
+                    )
                     .route(
                         "/links/{id}/sources",
                         web::get().to(handlers::get_link_sources),
                     )
                     .route("/links/{id}", web::patch().to(handlers::edit_link))
+                    .route(
+                        "/links/{id}/disable",
+                        web::post().to(handlers::disable_link),
+                    )
+                    .route("/links/{id}/enable", web::post().to(handlers::enable_link))
+                    .route("/links/{id}/qr", web::get().to(handlers::get_link_qr))
+                    .route(
+                        "/links/{id}/stats/share",
+                        web::get().to(handlers::get_link_stats_share),
+                    )
+                    .route(
+                        "/links/{id}/stats/share",
+                        web::post().to(handlers::share_link_stats),
+                    )
+                    .route(
+                        "/links/{id}/stats/share",
+                        web::delete().to(handlers::revoke_link_stats_share),
+                    )
+                    .route("/tags", web::get().to(handlers::list_tags))
+                    .route("/tags", web::post().to(handlers::create_tag))
+                    .route("/tags/{id}", web::patch().to(handlers::rename_tag))
+                    .route("/tags/{id}", web::delete().to(handlers::delete_tag))
+                    .route("/tags/{id}/clicks", web::get().to(handlers::get_tag_clicks))
+                    .route(
+                        "/report/challenge",
+                        web::get().to(handlers::get_report_challenge),
+                    )
+                    .route("/report", web::post().to(handlers::report_link))
+                    .route("/admin/reports", web::get().to(handlers::list_reports))
+                    .route(
+                        "/admin/reports/{id}/dismiss",
+                        web::post().to(handlers::dismiss_report),
+                    )
+                    .route(
+                        "/admin/links/{id}/disable",
+                        web::post().to(handlers::admin_disable_link),
+                    )
+                    .route("/admin/users/{id}/ban", web::post().to(handlers::ban_user))
+                    .route(
+                        "/admin/users/{id}/ban",
+                        web::delete().to(handlers::unban_user),
+                    )
+                    .route(
+                        "/admin/domain-rules",
+                        web::get().to(handlers::list_domain_rules),
+                    )
+                    .route(
+                        "/admin/domain-rules",
+                        web::post().to(handlers::add_domain_rule),
+                    )
+                    .route(
+                        "/admin/domain-rules/{id}",
+                        web::delete().to(handlers::remove_domain_rule),
+                    )
+                    .route(
+                        "/admin/links/flagged",
+                        web::get().to(handlers::list_flagged_links),
+                    )
+                    .route(
+                        "/admin/tombstones",
+                        web::get().to(handlers::list_tombstones),
+                    )
+                    .route(
+                        "/admin/tombstones/{code}",
+                        web::delete().to(handlers::release_tombstone),
+                    )
                     .route("/auth/register", web::post().to(handlers::register))
                     .route("/auth/login", web::post().to(handlers::login))
+                    .route("/users/me", web::patch().to(handlers::update_profile))
                     .route(
                         "/auth/check-first-user",
                         web::get().to(handlers::check_first_user),
                     )
                     .route("/health", web::get().to(handlers::health_check)),
             )
+            .service(
+                web::resource("/{short_code}/badge.svg")
+                    .route(web::get().to(handlers::get_link_badge)),
+            )
+            .service(
+                web::resource("/{short_code}/stats")
+                    .route(web::get().to(handlers::public_link_stats)),
+            )
+            .service(
+                web::resource("/{short_code}/sparkline.svg")
+                    .route(web::get().to(handlers::get_link_sparkline)),
+            )
+            .service(
+                web::resource("/{short_code}.qr").route(web::get().to(handlers::public_link_qr)),
+            )
             .service(web::resource("/{short_code}").route(web::get().to(handlers::redirect_to_url)))
             .default_service(web::route().to(|req: actix_web::HttpRequest| async move {
                 let path = req.path().trim_start_matches('/');
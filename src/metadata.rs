@@ -0,0 +1,646 @@
+//! Background fetching of destination page metadata. When a link is created
+//! without a title, its destination is fetched once and the page's title,
+//! description and favicon are stored on the link.
+//!
+//! Fetches are made with strict timeouts and a body size cap, and refuse to
+//! connect to loopback, private or otherwise non-public addresses, including
+//! through redirects and DNS names that resolve to such addresses.
+
+use crate::models::DatabasePool;
+use crate::text::{decode_entities, html_attributes, MAX_DESCRIPTION_LEN, MAX_TITLE_LEN};
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{redirect, Url};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Semaphore};
+
+const QUEUE_SIZE: usize = 1000;
+const MAX_CONCURRENT_FETCHES: usize = 4;
+const MAX_REDIRECTS: usize = 3;
+const MAX_BODY_BYTES: usize = 512 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(8);
+
+lazy_static! {
+    static ref TITLE_REGEX: Regex = Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap();
+    static ref META_REGEX: Regex = Regex::new(r"(?is)<meta\s([^>]*)>").unwrap();
+    static ref HEAD_END_REGEX: Regex = Regex::new(r"(?i)</head>").unwrap();
+    static ref LINK_REGEX: Regex = Regex::new(r"(?is)<link\s([^>]*)>").unwrap();
+}
+
+struct MetadataJob {
+    link_id: i32,
+    url: String,
+}
+
+/// Handle for queueing metadata fetches. Cheap to clone; when fetching is
+/// disabled, queued jobs are dropped.
+#[derive(Clone)]
+pub struct MetadataFetcher {
+    sender: Option<mpsc::Sender<MetadataJob>>,
+}
+
+impl MetadataFetcher {
+    /// Starts the background worker, configured from `METADATA_FETCH` (set to
+    /// `false` to turn fetching off) and `METADATA_FETCH_ALLOW_PRIVATE` (set to
+    /// `true` to allow intranet destinations).
+    pub fn from_env(db: DatabasePool) -> anyhow::Result<Self> {
+        let enabled = std::env::var("METADATA_FETCH").map_or(true, |v| v != "false");
+        if !enabled {
+            tracing::info!("Destination metadata fetching is disabled");
+            return Ok(Self::disabled());
+        }
+        let allow_private =
+            std::env::var("METADATA_FETCH_ALLOW_PRIVATE").is_ok_and(|v| v == "true");
+        Self::start(db, allow_private)
+    }
+
+    pub fn start(db: DatabasePool, allow_private: bool) -> anyhow::Result<Self> {
+        let client = build_client(allow_private)?;
+        let (sender, mut receiver) = mpsc::channel::<MetadataJob>(QUEUE_SIZE);
+
+        tokio::spawn(async move {
+            let limit = Arc::new(Semaphore::new(MAX_CONCURRENT_FETCHES));
+            while let Some(job) = receiver.recv().await {
+                let Ok(permit) = limit.clone().acquire_owned().await else {
+                    break;
+                };
+                let db = db.clone();
+                let client = client.clone();
+                tokio::spawn(async move {
+                    let _permit = permit;
+                    if let Err(err) = run_job(&db, &client, &job, allow_private).await {
+                        tracing::debug!("Metadata fetch for link {} failed: {}", job.link_id, err);
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            sender: Some(sender),
+        })
+    }
+
+    pub fn disabled() -> Self {
+        Self { sender: None }
+    }
+
+    /// Queues a fetch for `link_id`. Never waits: if the queue is full the
+    /// job is dropped and the link simply stays without a title.
+    pub fn enqueue(&self, link_id: i32, url: &str) {
+        if let Some(sender) = &self.sender {
+            let job = MetadataJob {
+                link_id,
+                url: url.to_string(),
+            };
+            if sender.try_send(job).is_err() {
+                tracing::warn!("Metadata queue is full, skipping link {}", link_id);
+            }
+        }
+    }
+}
+
+fn build_client(allow_private: bool) -> reqwest::Result<reqwest::Client> {
+    let policy = redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if let Err(reason) = check_url(attempt.url(), allow_private) {
+            attempt.error(reason)
+        } else {
+            attempt.follow()
+        }
+    });
+
+    reqwest::Client::builder()
+        .user_agent(concat!(
+            "SimpleLink/",
+            env!("CARGO_PKG_VERSION"),
+            " (link preview)"
+        ))
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(policy)
+        .no_proxy()
+        .dns_resolver(Arc::new(PublicResolver { allow_private }))
+        .build()
+}
+
+/// Rejects non-web schemes and literal non-public IP addresses. Host names
+/// are checked when `PublicResolver` resolves them.
+fn check_url(url: &Url, allow_private: bool) -> Result<(), &'static str> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("unsupported scheme");
+    }
+    let host = url.host_str().ok_or("missing host")?;
+    let Ok(ip) = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    else {
+        return Ok(());
+    };
+    if allow_private || is_public_ip(ip) {
+        Ok(())
+    } else {
+        Err("destination is not a public address")
+    }
+}
+
+/// DNS resolver that drops non-public addresses, so a public-looking name
+/// can't be used to reach internal services.
+struct PublicResolver {
+    allow_private: bool,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let allow_private = self.allow_private;
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| allow_private || is_public_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(
+                    format!("{} does not resolve to a public address", name.as_str()).into(),
+                );
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether `ip` is a globally routable unicast address. IPv6 addresses that
+/// embed an IPv4 address are judged by the address they embed.
+pub(crate) fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match embedded_ipv4(ip) {
+            Some(embedded) => is_public_ipv4(embedded),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+/// The IPv4 address carried by an IPv4-mapped (`::ffff:a.b.c.d`),
+/// IPv4-compatible (`::a.b.c.d`), NAT64 (`64:ff9b::/96`) or 6to4
+/// (`2002::/16`) address.
+fn embedded_ipv4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let segments = ip.segments();
+    let octets = ip.octets();
+    if let Some(ipv4) = ip.to_ipv4() {
+        // `::` and `::1` come out as 0.0.0.0 and 0.0.0.1, which aren't
+        // public either
+        return Some(ipv4);
+    }
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        return Some(Ipv4Addr::new(
+            octets[12], octets[13], octets[14], octets[15],
+        ));
+    }
+    if segments[0] == 0x2002 {
+        return Some(Ipv4Addr::new(octets[2], octets[3], octets[4], octets[5]));
+    }
+    None
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        // Carrier-grade NAT, 100.64.0.0/10
+        || (a == 100 && (64..128).contains(&b))
+        // IETF protocol assignments, 192.0.0.0/24
+        || (a == 192 && b == 0 && c == 0)
+        // Benchmarking, 198.18.0.0/15
+        || (a == 198 && (b == 18 || b == 19))
+        // Reserved, 240.0.0.0/4
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let [first, second, ..] = ip.segments();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Local-use NAT64, 64:ff9b:1::/48
+        || (first == 0x64 && second == 0xff9b)
+        // Unique local, fc00::/7
+        || (first & 0xfe00) == 0xfc00
+        // Link local, fe80::/10
+        || (first & 0xffc0) == 0xfe80
+        // Documentation, 2001:db8::/32
+        || (first == 0x2001 && second == 0x0db8))
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct PageMetadata {
+    title: Option<String>,
+    description: Option<String>,
+    favicon_url: Option<String>,
+}
+
+async fn run_job(
+    db: &DatabasePool,
+    client: &reqwest::Client,
+    job: &MetadataJob,
+    allow_private: bool,
+) -> anyhow::Result<()> {
+    let url = Url::parse(&job.url)?;
+    check_url(&url, allow_private).map_err(|reason| anyhow::anyhow!(reason))?;
+
+    let Some(metadata) = fetch(client, url).await? else {
+        return Ok(());
+    };
+
+    // Only fill in what is still missing, and only if the destination
+    // hasn't been edited while the fetch was running
+    match db {
+        DatabasePool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                UPDATE links
+                SET title = COALESCE(title, $1),
+                    description = COALESCE(description, $2),
+                    favicon_url = COALESCE($3, favicon_url),
+                    version = version + 1
+                WHERE id = $4 AND original_url = $5
+                "#,
+            )
+            .bind(&metadata.title)
+            .bind(&metadata.description)
+            .bind(&metadata.favicon_url)
+            .bind(job.link_id)
+            .bind(&job.url)
+            .execute(pool)
+            .await?;
+        }
+        DatabasePool::Sqlite(pool) => {
+            sqlx::query(
+                r#"
+                UPDATE links
+                SET title = COALESCE(title, ?1),
+                    description = COALESCE(description, ?2),
+                    favicon_url = COALESCE(?3, favicon_url),
+                    version = version + 1
+                WHERE id = ?4 AND original_url = ?5
+                "#,
+            )
+            .bind(&metadata.title)
+            .bind(&metadata.description)
+            .bind(&metadata.favicon_url)
+            .bind(job.link_id)
+            .bind(&job.url)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Fetches `url` and parses its metadata. Returns `None` for responses that
+/// aren't successful HTML pages.
+async fn fetch(client: &reqwest::Client, url: Url) -> anyhow::Result<Option<PageMetadata>> {
+    let mut response = client.get(url).send().await?;
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+    let is_html = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("text/html") || ct.starts_with("application/xhtml"));
+    if !is_html {
+        return Ok(None);
+    }
+
+    // Everything we need is in <head>, so a truncated body is fine
+    let base = response.url().clone();
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_BODY_BYTES {
+            body.truncate(MAX_BODY_BYTES);
+            break;
+        }
+    }
+
+    Ok(Some(parse_page(&String::from_utf8_lossy(&body), &base)))
+}
+
+fn parse_page(html: &str, base: &Url) -> PageMetadata {
+    let head = HEAD_END_REGEX
+        .find(html)
+        .map_or(html, |end| &html[..end.start()]);
+
+    let mut og_title = None;
+    let mut og_description = None;
+    let mut description = None;
+    for captures in META_REGEX.captures_iter(head) {
+        let attributes = html_attributes(&captures[1]);
+        let get = |key: &str| {
+            attributes
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.as_str())
+        };
+        let Some(content) = get("content").and_then(clean_text) else {
+            continue;
+        };
+        match get("property")
+            .or_else(|| get("name"))
+            .map(str::to_lowercase)
+            .as_deref()
+        {
+            Some("og:title") => og_title = og_title.or(Some(content)),
+            Some("og:description") => og_description = og_description.or(Some(content)),
+            Some("description") => description = description.or(Some(content)),
+            _ => {}
+        }
+    }
+
+    let title = og_title.or_else(|| {
+        TITLE_REGEX
+            .captures(head)
+            .and_then(|c| clean_text(&decode_entities(&c[1])))
+    });
+
+    let favicon_url = LINK_REGEX
+        .captures_iter(head)
+        .map(|captures| html_attributes(&captures[1]))
+        .filter(|attributes| {
+            attributes.iter().any(|(name, value)| {
+                name == "rel"
+                    && value
+                        .split_whitespace()
+                        .any(|rel| rel.eq_ignore_ascii_case("icon"))
+            })
+        })
+        .find_map(|attributes| {
+            attributes
+                .into_iter()
+                .find(|(name, _)| name == "href")
+                .and_then(|(_, href)| base.join(&href).ok())
+        })
+        .or_else(|| base.join("/favicon.ico").ok())
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .map(String::from);
+
+    PageMetadata {
+        title: title.map(|t| truncate(t, MAX_TITLE_LEN)),
+        description: og_description
+            .or(description)
+            .map(|d| truncate(d, MAX_DESCRIPTION_LEN)),
+        favicon_url,
+    }
+}
+
+/// Collapses runs of whitespace, returning `None` for blank text.
+fn clean_text(text: &str) -> Option<String> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+fn truncate(text: String, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => text[..end].to_string(),
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn page(html: &str) -> PageMetadata {
+        parse_page(html, &Url::parse("https://example.com/blog/post").unwrap())
+    }
+
+    #[test]
+    fn parse_page_prefers_open_graph() {
+        let metadata = page(
+            r#"<html><head>
+            <title>Plain title</title>
+            <meta name="description" content="Plain description">
+            <meta property="og:title" content="OG &amp; title">
+            <meta property="og:description" content="  OG
+                description ">
+            <link rel="shortcut icon" href="/icon.png">
+            </head><body></body></html>"#,
+        );
+        assert_eq!(
+            metadata,
+            PageMetadata {
+                title: Some("OG & title".to_string()),
+                description: Some("OG description".to_string()),
+                favicon_url: Some("https://example.com/icon.png".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_page_falls_back_to_title_and_default_favicon() {
+        let metadata = page(
+            "<head><TITLE>Caf&eacute; &#8212; menu</TITLE>\
+             <meta name=\"description\" content=\"Lunch\"></head>",
+        );
+        assert_eq!(metadata.title.as_deref(), Some("Caf&eacute; \u{2014} menu"));
+        assert_eq!(metadata.description.as_deref(), Some("Lunch"));
+        assert_eq!(
+            metadata.favicon_url.as_deref(),
+            Some("https://example.com/favicon.ico")
+        );
+    }
+
+    #[test]
+    fn parse_page_ignores_the_body_and_caps_lengths() {
+        let long = "x".repeat(MAX_TITLE_LEN + 50);
+        let metadata = page(&format!(
+            "<head><title>{}</title></head><body><meta property=\"og:title\" content=\"Body\"></body>",
+            long
+        ));
+        assert_eq!(metadata.title.map(|t| t.len()), Some(MAX_TITLE_LEN));
+        assert_eq!(page("<head><title> </title></head>").title, None);
+    }
+
+    #[test]
+    fn parse_page_skips_non_web_favicons() {
+        let metadata = page(r#"<head><link rel="icon" href="data:image/png;base64,AAAA"></head>"#);
+        assert_eq!(metadata.favicon_url, None);
+    }
+
+    #[test]
+    fn public_addresses() {
+        for ip in [
+            "1.1.1.1",
+            "93.184.216.34",
+            "2606:4700:4700::1111",
+            "::ffff:1.1.1.1",
+            "64:ff9b::101:101",
+            "2002:101:101::1",
+        ] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{} should be public", ip);
+        }
+    }
+
+    #[test]
+    fn non_public_addresses() {
+        for ip in [
+            "0.0.0.0",
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "192.0.0.8",
+            "192.0.2.1",
+            "198.18.0.1",
+            "224.0.0.1",
+            "255.255.255.255",
+            "::",
+            "::1",
+            "fc00::1",
+            "fe80::1",
+            "2001:db8::1",
+            "ff02::1",
+            // IPv4-mapped and IPv4-compatible
+            "::ffff:127.0.0.1",
+            "::10.0.0.1",
+            "::169.254.169.254",
+            // NAT64
+            "64:ff9b::a00:1",
+            "64:ff9b::7f00:1",
+            "64:ff9b:1::1",
+            // 6to4
+            "2002:a00:1::1",
+            "2002:7f00:1::",
+            "2002:c0a8:101::1",
+        ] {
+            assert!(
+                !is_public_ip(ip.parse().unwrap()),
+                "{} should not be public",
+                ip
+            );
+        }
+    }
+
+    #[test]
+    fn check_url_rejects_schemes_and_private_literals() {
+        let check = |url: &str, allow_private| check_url(&Url::parse(url).unwrap(), allow_private);
+        assert!(check("https://example.com/", false).is_ok());
+        assert!(check("ftp://example.com/", false).is_err());
+        assert!(check("file:///etc/passwd", true).is_err());
+        assert!(check("http://127.0.0.1/", false).is_err());
+        assert!(check("http://[::ffff:10.0.0.1]/", false).is_err());
+        assert!(check("http://[64:ff9b::a9fe:a9fe]/", false).is_err());
+        assert!(check("http://127.0.0.1/", true).is_ok());
+    }
+
+    /// Serves `respond(path)` as the raw HTTP response to each request on a
+    /// local port, standing in for a destination site.
+    async fn stand_in(respond: fn(&str) -> String) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match socket.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request);
+                    let path = request.split_whitespace().nth(1).unwrap_or("/");
+                    let _ = socket.write_all(respond(path).as_bytes()).await;
+                });
+            }
+        });
+        addr
+    }
+
+    fn html_response(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    }
+
+    fn redirect_response(location: &str) -> String {
+        format!(
+            "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            location
+        )
+    }
+
+    fn site(path: &str) -> String {
+        match path {
+            "/" => html_response(
+                r#"<head><title>Stand-in</title><link rel="icon" href="/i.png"></head>"#,
+            ),
+            "/hop" => redirect_response("/"),
+            "/loop" => redirect_response("/loop"),
+            "/ftp" => redirect_response("ftp://example.com/"),
+            "/json" => "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}".to_string(),
+            "/big" => html_response(&format!(
+                "<head><title>Big</title></head>{}",
+                " ".repeat(MAX_BODY_BYTES * 2)
+            )),
+            _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn fetches_and_follows_redirects() {
+        let addr = stand_in(site).await;
+        let client = build_client(true).unwrap();
+        let url = |path: &str| Url::parse(&format!("http://{}{}", addr, path)).unwrap();
+
+        let metadata = fetch(&client, url("/hop")).await.unwrap().unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Stand-in"));
+        assert_eq!(metadata.favicon_url, Some(format!("http://{}/i.png", addr)));
+
+        let big = fetch(&client, url("/big")).await.unwrap().unwrap();
+        assert_eq!(big.title.as_deref(), Some("Big"));
+
+        assert_eq!(fetch(&client, url("/json")).await.unwrap(), None);
+        assert_eq!(fetch(&client, url("/missing")).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn redirect_policy_limits_hops_and_schemes() {
+        let addr = stand_in(site).await;
+        let client = build_client(true).unwrap();
+        let url = |path: &str| Url::parse(&format!("http://{}{}", addr, path)).unwrap();
+
+        assert!(fetch(&client, url("/loop")).await.is_err());
+        assert!(fetch(&client, url("/ftp")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn resolver_refuses_names_of_private_addresses() {
+        let addr = stand_in(site).await;
+        let url = Url::parse(&format!("http://localhost:{}/", addr.port())).unwrap();
+
+        let private = build_client(false).unwrap();
+        assert!(fetch(&private, url.clone()).await.is_err());
+
+        let allowed = build_client(true).unwrap();
+        assert!(fetch(&allowed, url).await.unwrap().is_some());
+    }
+}
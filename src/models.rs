@@ -93,11 +93,163 @@ impl Claims {
     }
 }
 
+/// What a link's stats share token is signed over. It has no expiry, share
+/// links last until the owner revokes them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatsShareClaims {
+    /// Always `stats-share`, so that no other token signed with the same
+    /// secret passes as a share token, nor a share token as another token
+    pub aud: String,
+    pub link: i32,
+    pub nonce: String,
+}
+
+#[derive(Serialize)]
+pub struct StatsShare {
+    pub token: String,
+    /// Path of the public stats page
+    pub url: String,
+}
+
+#[derive(Deserialize)]
+pub struct PublicStatsQuery {
+    /// A share token from `POST /api/links/{id}/stats/share`
+    pub share: Option<String>,
+    #[serde(default)]
+    pub format: StatsFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsFormat {
+    #[default]
+    Html,
+    Json,
+}
+
+/// The aggregated stats on a public stats page.
+#[derive(Serialize)]
+pub struct PublicStats {
+    pub short_code: String,
+    pub title: Option<String>,
+    pub total_clicks: i64,
+    pub clicks: Vec<ClickStats>,
+    pub sources: Vec<SourceStats>,
+}
+
 #[derive(Deserialize)]
 pub struct CreateLink {
     pub url: String,
     pub source: Option<String>,
     pub custom_code: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// Private to the link's owner
+    pub notes: Option<String>,
+    /// Tag names to attach
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    /// Return the caller's existing link to the same URL instead of creating
+    /// a new one. Defaults to `DEDUPE_LINKS`.
+    pub dedupe: Option<bool>,
+    /// Show visitors a preview page instead of redirecting straight away
+    pub preview: Option<bool>,
+    /// Shown by Slack, Twitter and the like when the short link is shared
+    pub og_title: Option<String>,
+    pub og_description: Option<String>,
+    pub og_image: Option<String>,
+    /// Serve the public click badge and sparkline
+    pub embeddable: Option<bool>,
+    /// Publish the link's stats at `/{short_code}/stats`
+    pub public_stats: Option<bool>,
+}
+
+/// A partial update to a link. Fields that are left out keep their value.
+#[derive(Deserialize)]
+pub struct UpdateLink {
+    pub url: Option<String>,
+    pub custom_code: Option<String>,
+    /// An empty string clears the title, and likewise for description and notes
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub notes: Option<String>,
+    /// Replaces the link's tags
+    pub tags: Option<Vec<String>>,
+    pub preview: Option<bool>,
+    pub og_title: Option<String>,
+    pub og_description: Option<String>,
+    pub og_image: Option<String>,
+    pub embeddable: Option<bool>,
+    pub public_stats: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkMode {
+    /// Create every link or none of them
+    #[default]
+    Atomic,
+    /// Create whatever links are valid and report the rest
+    BestEffort,
+}
+
+#[derive(Deserialize)]
+pub struct BulkQuery {
+    #[serde(default)]
+    pub mode: BulkMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkStatus {
+    Created,
+    Failed,
+    RolledBack,
+}
+
+#[derive(Serialize)]
+pub struct BulkLinkResult {
+    pub index: usize,
+    pub status: BulkStatus,
+    pub link: Option<Link>,
+    pub error: Option<String>,
+}
+
+impl BulkLinkResult {
+    pub fn created(index: usize, link: Link) -> Self {
+        Self {
+            index,
+            status: BulkStatus::Created,
+            link: Some(link),
+            error: None,
+        }
+    }
+
+    pub fn failed(index: usize, error: String) -> Self {
+        Self {
+            index,
+            status: BulkStatus::Failed,
+            link: None,
+            error: Some(error),
+        }
+    }
+
+    pub fn rolled_back(index: usize) -> Self {
+        Self {
+            index,
+            status: BulkStatus::RolledBack,
+            link: None,
+            error: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct BulkCreateResponse {
+    pub mode: BulkMode,
+    pub created: usize,
+    pub failed: usize,
+    pub results: Vec<BulkLinkResult>,
 }
 
 #[derive(Serialize, FromRow)]
@@ -108,6 +260,412 @@ pub struct Link {
     pub short_code: String,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub clicks: i64,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub notes: Option<String>,
+    /// Set by the background metadata fetcher
+    pub favicon_url: Option<String>,
+    /// Set while the link is in the trash
+    pub deleted_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Bumped on every edit, sent as the link's `ETag`
+    pub version: i32,
+    /// Set while the destination breaks a domain rule or is on a blocklist
+    pub flagged_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub flag_reason: Option<String>,
+    /// Disabled links don't redirect
+    pub enabled: bool,
+    pub disabled_reason: Option<String>,
+    pub disabled_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// The user who disabled the link, `None` if a blocklist did
+    pub disabled_by: Option<i32>,
+    /// Visitors see a preview page and have to confirm before being redirected
+    pub preview: bool,
+    /// Open Graph overrides served to link unfurling crawlers
+    pub og_title: Option<String>,
+    pub og_description: Option<String>,
+    pub og_image: Option<String>,
+    /// Whether the public click badge and sparkline are served
+    pub embeddable: bool,
+    /// Whether the stats page at `/{short_code}/stats` is public
+    pub public_stats: bool,
+    /// Share tokens are only valid while they carry this nonce
+    #[serde(skip_serializing)]
+    pub stats_share_nonce: Option<String>,
+    /// Filled in separately from the `link_tags` join table
+    #[sqlx(skip)]
+    pub tags: Vec<String>,
+}
+
+/// A change to a link's destination or short code made through `edit_link`.
+#[derive(Serialize, FromRow)]
+pub struct LinkRevision {
+    pub id: i32,
+    pub link_id: i32,
+    /// Who made the change
+    pub user_id: Option<i32>,
+    pub old_url: String,
+    pub new_url: String,
+    pub old_code: String,
+    pub new_code: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A code that resolves to a link: its short code or one of its aliases.
+#[derive(Serialize, FromRow)]
+pub struct LinkCode {
+    pub short_code: String,
+    /// Whether this is the link's own `short_code` rather than an alias
+    pub is_primary: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Clicks that came in through this code
+    pub clicks: i64,
+}
+
+#[derive(Deserialize)]
+pub struct AliasRequest {
+    pub short_code: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkSort {
+    #[default]
+    CreatedAt,
+    Clicks,
+    ShortCode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Deserialize, Default)]
+pub struct LinkListQuery {
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+    pub q: Option<String>,
+    pub created_after: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_before: Option<chrono::DateTime<chrono::Utc>>,
+    pub min_clicks: Option<i64>,
+    pub max_clicks: Option<i64>,
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub sort: LinkSort,
+    #[serde(default)]
+    pub order: SortOrder,
+}
+
+#[derive(Serialize)]
+pub struct LinkPage {
+    pub links: Vec<Link>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, FromRow)]
+pub struct Tag {
+    pub id: i32,
+    pub name: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A tag together with totals over the links that carry it.
+#[derive(Serialize, FromRow)]
+pub struct TagSummary {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub tag: Tag,
+    pub link_count: i64,
+    pub clicks: i64,
+}
+
+#[derive(Deserialize)]
+pub struct TagRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    Allow,
+    Deny,
+}
+
+impl RuleAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RuleAction::Allow => "allow",
+            RuleAction::Deny => "deny",
+        }
+    }
+}
+
+/// An allow or deny rule for destination hosts.
+#[derive(Serialize, FromRow)]
+pub struct DomainRule {
+    pub id: i32,
+    /// `None` for rules that apply to every user
+    pub user_id: Option<i32>,
+    /// A host such as `example.com`, or `*.example.com` for its subdomains
+    pub pattern: String,
+    /// `allow` or `deny`
+    pub action: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct DomainRuleRequest {
+    pub pattern: String,
+    pub action: RuleAction,
+    /// Limits the rule to one user's links
+    pub user_id: Option<i32>,
+}
+
+/// An abuse report sent through `/api/report`.
+#[derive(Serialize, FromRow)]
+pub struct AbuseReport {
+    pub id: i32,
+    pub link_id: i32,
+    /// The code that was reported, which may be an alias
+    pub short_code: String,
+    pub reason: String,
+    pub reporter_ip: Option<String>,
+    /// `open`, `dismissed` or `actioned`
+    pub status: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub resolved_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub resolved_by: Option<i32>,
+    /// Filled in from the reported link
+    pub original_url: String,
+    pub owner_id: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct ReportChallenge {
+    pub challenge: String,
+    /// Leading zero bits SHA-256 of `challenge` followed by `nonce` must have
+    pub difficulty: u32,
+    pub expires_in: u64,
+}
+
+#[derive(Deserialize)]
+pub struct ReportRequest {
+    pub short_code: String,
+    pub reason: String,
+    pub challenge: String,
+    pub nonce: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportStatusFilter {
+    #[default]
+    Open,
+    Dismissed,
+    Actioned,
+    All,
+}
+
+#[derive(Deserialize)]
+pub struct ReportListQuery {
+    #[serde(default)]
+    pub status: ReportStatusFilter,
+}
+
+/// Body of the endpoints that disable a link or ban a user.
+#[derive(Deserialize, Default)]
+pub struct ModerationRequest {
+    pub reason: Option<String>,
+}
+
+/// A short code that stays reserved after its link went away.
+#[derive(Serialize, FromRow)]
+pub struct CodeTombstone {
+    pub short_code: String,
+    /// The last owner, who may still reuse the code
+    pub user_id: Option<i32>,
+    pub released_at: chrono::DateTime<chrono::Utc>,
+    /// `None` while the code is retired for good
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Serialize, FromRow)]
+pub struct LinkSearchResult {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub link: Link,
+    pub rank: f64,
+}
+
+/// A link in the portable export format. Also the unit that imports work
+/// with, whatever file format they started from.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ExportedLink {
+    pub short_code: String,
+    pub original_url: String,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub clicks: i64,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LinkExport {
+    pub version: u32,
+    pub exported_at: chrono::DateTime<chrono::Utc>,
+    pub links: Vec<ExportedLink>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QrFormat {
+    #[default]
+    Png,
+    Svg,
+}
+
+/// QR code error correction levels, recovering about 7%, 15%, 25% and 30%
+/// of the code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QrEcc {
+    L,
+    M,
+    Q,
+    H,
+}
+
+#[derive(Deserialize)]
+pub struct QrQuery {
+    #[serde(default)]
+    pub format: QrFormat,
+    /// Width and height in pixels
+    pub size: Option<u32>,
+    pub ecc: Option<QrEcc>,
+    /// Quiet zone around the code, in modules
+    pub margin: Option<u32>,
+    /// Hex colours of the dark and light modules
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    /// Put the server's `QR_LOGO` in the middle
+    #[serde(default)]
+    pub logo: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStrategy {
+    /// Leave the existing link alone
+    #[default]
+    Skip,
+    /// Replace the existing link, only allowed for the caller's own links
+    Overwrite,
+    /// Import under a new, unused code
+    Rename,
+}
+
+/// File formats accepted by the import endpoint: SimpleLink's own exports
+/// plus those of the shorteners people commonly migrate from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportFormat {
+    Json,
+    Csv,
+    YourlsSql,
+    YourlsCsv,
+    ShlinkCsv,
+    BitlyCsv,
+    NetscapeHtml,
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    pub format: Option<ImportFormat>,
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub on_conflict: ConflictStrategy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStatus {
+    Created,
+    Overwritten,
+    Renamed,
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize)]
+pub struct ImportResult {
+    pub index: usize,
+    pub short_code: Option<String>,
+    pub status: ImportStatus,
+    pub new_code: Option<String>,
+    pub error: Option<String>,
+}
+
+impl ImportResult {
+    pub fn new(index: usize, short_code: String, status: ImportStatus) -> Self {
+        Self {
+            index,
+            short_code: Some(short_code),
+            status,
+            new_code: None,
+            error: None,
+        }
+    }
+
+    pub fn failed(index: usize, short_code: Option<String>, error: String) -> Self {
+        Self {
+            index,
+            short_code,
+            status: ImportStatus::Failed,
+            new_code: None,
+            error: Some(error),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ImportResponse {
+    pub dry_run: bool,
+    pub created: usize,
+    pub overwritten: usize,
+    pub renamed: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub results: Vec<ImportResult>,
 }
 
 #[derive(Deserialize)]
@@ -133,6 +691,7 @@ pub struct AuthResponse {
 pub struct UserResponse {
     pub id: i32,
     pub email: String,
+    pub display_name: Option<String>,
 }
 
 #[derive(FromRow)]
@@ -140,6 +699,14 @@ pub struct User {
     pub id: i32,
     pub email: String,
     pub password_hash: String,
+    /// Shown to visitors on link preview pages
+    pub display_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateProfile {
+    /// An empty string clears the display name
+    pub display_name: Option<String>,
 }
 
 #[derive(sqlx::FromRow, Serialize)]
@@ -148,6 +715,24 @@ pub struct ClickStats {
     pub clicks: i64,
 }
 
+#[derive(Deserialize)]
+pub struct BadgeQuery {
+    /// Text on the left, `clicks` by default
+    pub label: Option<String>,
+    /// Hex colour of the right-hand side
+    pub color: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SparklineQuery {
+    /// How many days back the sparkline goes, including today
+    pub days: Option<u32>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Hex colour of the line
+    pub color: Option<String>,
+}
+
 #[derive(sqlx::FromRow, Serialize)]
 pub struct SourceStats {
     pub date: String,
@@ -0,0 +1,306 @@
+//! Small HTML pages served to visitors of short links instead of a
+//! redirect.
+
+use crate::Asset;
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use anyhow::{bail, Context};
+use std::sync::Arc;
+
+/// The preview and stats page templates, built from `frontend/public`
+const PREVIEW_TEMPLATE: &str = "preview.html";
+const STATS_TEMPLATE: &str = "stats.html";
+
+const DISABLED_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="robots" content="noindex">
+<title>Link disabled</title>
+<style>
+body { font-family: system-ui, sans-serif; max-width: 32rem; margin: 4rem auto; padding: 0 1rem; color: #222; }
+h1 { font-size: 1.5rem; }
+p { line-height: 1.5; color: #555; }
+</style>
+</head>
+<body>
+<h1>This link has been disabled</h1>
+<p>The short link you followed has been disabled, for example because it was reported for abuse or pointed at a harmful site.</p>
+</body>
+</html>
+"#;
+
+const UNFURL_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="robots" content="noindex">
+<title>{{title}}</title>
+<meta property="og:type" content="website">
+<meta property="og:url" content="{{url}}">
+<meta property="og:title" content="{{title}}">
+{{#description}}<meta property="og:description" content="{{description}}">
+{{/description}}{{#image}}<meta property="og:image" content="{{image}}">
+<meta name="twitter:card" content="summary_large_image">
+{{/image}}</head>
+<body>
+<a href="{{destination}}">{{title}}</a>
+</body>
+</html>
+"#;
+
+/// User agents of the bots that fetch links to render previews of them in
+/// chats and feeds, matched case-insensitively
+const UNFURL_CRAWLERS: &[&str] = &[
+    "slackbot",
+    "twitterbot",
+    "linkedinbot",
+    "facebookexternalhit",
+    "facebot",
+    "discordbot",
+    "telegrambot",
+    "whatsapp",
+    "skypeuripreview",
+    "mattermost",
+    "pinterestbot",
+    "redditbot",
+    "embedly",
+    "iframely",
+];
+
+/// Whether a request comes from a bot building a link preview rather than
+/// from someone following the link.
+pub fn is_unfurl_crawler(user_agent: &str) -> bool {
+    let user_agent = user_agent.to_lowercase();
+    UNFURL_CRAWLERS
+        .iter()
+        .any(|crawler| user_agent.contains(crawler))
+}
+
+/// The Open Graph tags served to unfurling crawlers for links that override
+/// them.
+pub struct Unfurl<'a> {
+    /// The short link
+    pub url: &'a str,
+    pub destination: &'a str,
+    pub title: &'a str,
+    pub description: Option<&'a str>,
+    pub image: Option<&'a str>,
+}
+
+impl Unfurl<'_> {
+    pub fn render(&self) -> HttpResponse {
+        let body = render_template(
+            UNFURL_PAGE,
+            &[
+                ("url", Some(self.url)),
+                ("destination", Some(self.destination)),
+                ("title", Some(self.title)),
+                ("description", self.description),
+                ("image", self.image),
+            ],
+        );
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .insert_header(("Cache-Control", "no-store"))
+            .insert_header(("X-Robots-Tag", "noindex"))
+            .body(body)
+    }
+}
+
+/// What visitors of a disabled link get: `DISABLED_LINK_STATUS` (410 by
+/// default) and either the built-in page, the HTML file at
+/// `DISABLED_LINK_PAGE`, or no body at all if that is set to `none`.
+#[derive(Clone)]
+pub struct DisabledPage {
+    status: StatusCode,
+    body: Option<Arc<str>>,
+}
+
+impl DisabledPage {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let status = match std::env::var("DISABLED_LINK_STATUS") {
+            Ok(value) => match value.trim().parse::<u16>() {
+                Ok(code @ 400..=599) => StatusCode::from_u16(code)?,
+                _ => bail!("DISABLED_LINK_STATUS must be a status code from 400 to 599"),
+            },
+            Err(_) => StatusCode::GONE,
+        };
+        let body = match std::env::var("DISABLED_LINK_PAGE").as_deref() {
+            Err(_) => Some(Arc::from(DISABLED_PAGE)),
+            Ok("none") => None,
+            Ok(path) => Some(Arc::from(std::fs::read_to_string(path).with_context(
+                || format!("Failed to read DISABLED_LINK_PAGE {}", path),
+            )?)),
+        };
+        Ok(Self { status, body })
+    }
+
+    pub fn render(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status);
+        match &self.body {
+            Some(body) => response
+                .content_type("text/html; charset=utf-8")
+                .body(body.to_string()),
+            None => response.finish(),
+        }
+    }
+}
+
+/// The interstitial page shown for `/{code}+` and links with `preview` set.
+/// It shows where the link goes and only moves on when the visitor follows
+/// `continue_url`.
+pub struct Preview<'a> {
+    pub short_code: &'a str,
+    pub destination: &'a str,
+    pub title: Option<&'a str>,
+    /// The owner's display name
+    pub owner: Option<&'a str>,
+    pub continue_url: &'a str,
+}
+
+impl Preview<'_> {
+    pub fn render(&self) -> HttpResponse {
+        let Some(template) = embedded_template(PREVIEW_TEMPLATE) else {
+            return HttpResponse::InternalServerError().body("Link previews are unavailable");
+        };
+        let body = render_template(
+            &template,
+            &[
+                ("short_code", Some(self.short_code)),
+                ("destination", Some(self.destination)),
+                ("title", self.title),
+                ("owner", self.owner),
+                ("continue_url", Some(self.continue_url)),
+            ],
+        );
+
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .insert_header(("Cache-Control", "no-store"))
+            .insert_header(("X-Robots-Tag", "noindex, nofollow"))
+            .insert_header(("Referrer-Policy", "no-referrer"))
+            .insert_header((
+                "Content-Security-Policy",
+                "default-src 'none'; style-src 'unsafe-inline'; img-src 'self' data:; \
+                 base-uri 'none'; form-action 'none'; frame-ancestors 'none'",
+            ))
+            .body(body)
+    }
+}
+
+fn embedded_template(name: &str) -> Option<String> {
+    match Asset::get(name) {
+        Some(file) => Some(String::from_utf8_lossy(&file.data).into_owned()),
+        None => {
+            tracing::error!("{} is missing from the embedded frontend build", name);
+            None
+        }
+    }
+}
+
+/// A link's public stats page. The sparkline and table rows are HTML that
+/// the caller has already escaped.
+pub struct StatsPage<'a> {
+    pub short_code: &'a str,
+    pub title: Option<&'a str>,
+    pub total_clicks: &'a str,
+    pub sparkline: &'a str,
+    pub days: Option<&'a str>,
+    pub sources: Option<&'a str>,
+}
+
+impl StatsPage<'_> {
+    pub fn render(&self) -> HttpResponse {
+        let Some(template) = embedded_template(STATS_TEMPLATE) else {
+            return HttpResponse::InternalServerError().body("Stats pages are unavailable");
+        };
+        let body = render_template(
+            &template,
+            &[
+                ("short_code", Some(self.short_code)),
+                ("title", self.title),
+                ("total_clicks", Some(self.total_clicks)),
+                ("sparkline", Some(self.sparkline)),
+                ("days", self.days),
+                ("sources", self.sources),
+            ],
+        );
+
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .insert_header(("Cache-Control", "private, max-age=60"))
+            .insert_header(("X-Robots-Tag", "noindex, nofollow"))
+            .insert_header(("Referrer-Policy", "no-referrer"))
+            .insert_header((
+                "Content-Security-Policy",
+                "default-src 'none'; style-src 'unsafe-inline'; base-uri 'none'; \
+                 form-action 'none'; frame-ancestors 'none'",
+            ))
+            .body(body)
+    }
+}
+
+/// Fills in a template in a single pass. `{{name}}` is replaced by the
+/// HTML-escaped value, `{{{name}}}` by the value as it is, and
+/// `{{#name}}...{{/name}}` is left out when the value is missing.
+fn render_template(template: &str, values: &[(&str, Option<&str>)]) -> String {
+    let value = |name: &str| {
+        values
+            .iter()
+            .find(|(key, _)| *key == name)
+            .and_then(|(_, value)| *value)
+    };
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    // The section being left out, if any
+    let mut skipping: Option<&str> = None;
+    while let Some(start) = rest.find("{{") {
+        let raw = rest[start..].starts_with("{{{");
+        let (open, close) = if raw { ("{{{", "}}}") } else { ("{{", "}}") };
+        let Some(len) = rest[start + open.len()..].find(close) else {
+            break;
+        };
+        let tag = &rest[start + open.len()..start + open.len() + len];
+        if skipping.is_none() {
+            output.push_str(&rest[..start]);
+        }
+        if let Some(name) = tag.strip_prefix('#') {
+            if skipping.is_none() && value(name).is_none() {
+                skipping = Some(name);
+            }
+        } else if let Some(name) = tag.strip_prefix('/') {
+            if skipping == Some(name) {
+                skipping = None;
+            }
+        } else if skipping.is_none() {
+            let value = value(tag).unwrap_or_default();
+            if raw {
+                output.push_str(value);
+            } else {
+                output.push_str(&escape_html(value));
+            }
+        }
+        rest = &rest[start + open.len() + len + close.len()..];
+    }
+    if skipping.is_none() {
+        output.push_str(rest);
+    }
+    output
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
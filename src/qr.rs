@@ -0,0 +1,282 @@
+//! QR codes for short links, as PNG or SVG. The encoded URL carries
+//! `?source=qr`, so scans show up as `qr` in a link's click sources.
+//!
+//! A logo can be placed in the middle of the code. It is read once at
+//! startup from the PNG or JPEG file at `QR_LOGO`, and callers opt in with
+//! `logo=true`. Logos default to the highest error correction level so the
+//! modules they cover can be recovered.
+//!
+//! Codes are rendered on the blocking thread pool, since a large PNG with a
+//! logo takes a while. Public codes are capped at `MAX_PUBLIC_SIZE`.
+
+use crate::embeds::svg_paint;
+use crate::error::AppError;
+use crate::models::{QrEcc, QrFormat, QrQuery};
+use crate::text::parse_hex_color;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image::imageops::{self, FilterType};
+use image::{ImageFormat, Rgba, RgbaImage};
+use qrcode::{Color, EcLevel, QrCode};
+use std::fmt::Write;
+use std::io::Cursor;
+use std::sync::Arc;
+
+const DEFAULT_SIZE: u32 = 512;
+const MIN_SIZE: u32 = 64;
+const MAX_SIZE: u32 = 2048;
+/// The largest public code, at `/{short_code}.qr`
+const MAX_PUBLIC_SIZE: u32 = 1024;
+const DEFAULT_MARGIN: u32 = 4;
+const MAX_MARGIN: u32 = 16;
+/// How much of the code's width the logo takes up
+const LOGO_SCALE: f64 = 0.22;
+
+struct Logo {
+    image: RgbaImage,
+    /// The file as a `data:` URL, for SVG output
+    data_url: String,
+}
+
+/// The logo configured with `QR_LOGO`, if any. Cloning is cheap.
+#[derive(Clone, Default)]
+pub struct QrLogo(Option<Arc<Logo>>);
+
+impl QrLogo {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let Ok(path) = std::env::var("QR_LOGO") else {
+            return Ok(Self(None));
+        };
+        let bytes =
+            std::fs::read(&path).with_context(|| format!("Failed to read QR_LOGO {}", path))?;
+        let format = image::guess_format(&bytes)
+            .with_context(|| format!("QR_LOGO {} is not a PNG or JPEG image", path))?;
+        let image = image::load_from_memory_with_format(&bytes, format)
+            .with_context(|| format!("Failed to decode QR_LOGO {}", path))?
+            .to_rgba8();
+        let data_url = format!(
+            "data:{};base64,{}",
+            format.to_mime_type(),
+            STANDARD.encode(&bytes)
+        );
+        Ok(Self(Some(Arc::new(Logo { image, data_url }))))
+    }
+}
+
+/// Validated rendering options.
+pub struct QrStyle {
+    format: QrFormat,
+    size: u32,
+    ecc: EcLevel,
+    margin: u32,
+    dark: Rgba<u8>,
+    light: Rgba<u8>,
+    logo: Option<Arc<Logo>>,
+}
+
+impl QrStyle {
+    pub fn new(query: &QrQuery, logo: &QrLogo) -> Result<Self, AppError> {
+        let size = query.size.unwrap_or(DEFAULT_SIZE);
+        if !(MIN_SIZE..=MAX_SIZE).contains(&size) {
+            return Err(AppError::InvalidInput(format!(
+                "Size must be between {} and {} pixels",
+                MIN_SIZE, MAX_SIZE
+            )));
+        }
+        let margin = query.margin.unwrap_or(DEFAULT_MARGIN);
+        if margin > MAX_MARGIN {
+            return Err(AppError::InvalidInput(format!(
+                "Margin must be at most {} modules",
+                MAX_MARGIN
+            )));
+        }
+
+        let logo = if query.logo {
+            Some(logo.0.clone().ok_or_else(|| {
+                AppError::InvalidInput("No QR code logo is set up on this server".to_string())
+            })?)
+        } else {
+            None
+        };
+        let ecc = match query.ecc {
+            Some(QrEcc::L) => EcLevel::L,
+            Some(QrEcc::M) => EcLevel::M,
+            Some(QrEcc::Q) => EcLevel::Q,
+            Some(QrEcc::H) => EcLevel::H,
+            None if logo.is_some() => EcLevel::H,
+            None => EcLevel::M,
+        };
+
+        Ok(Self {
+            format: query.format,
+            size,
+            ecc,
+            margin,
+            dark: parse_color("fg", query.fg.as_deref(), Rgba([0, 0, 0, 255]))?,
+            light: parse_color("bg", query.bg.as_deref(), Rgba([255, 255, 255, 255]))?,
+            logo,
+        })
+    }
+
+    /// `new` for the public QR code, which anyone can ask for.
+    pub fn new_public(query: &QrQuery, logo: &QrLogo) -> Result<Self, AppError> {
+        let style = Self::new(query, logo)?;
+        if style.size > MAX_PUBLIC_SIZE {
+            return Err(AppError::InvalidInput(format!(
+                "Size must be between {} and {} pixels",
+                MIN_SIZE, MAX_PUBLIC_SIZE
+            )));
+        }
+        Ok(style)
+    }
+
+    /// Renders a QR code for `url` off the async workers.
+    pub async fn respond(self, url: String) -> Result<HttpResponse, AppError> {
+        let (content_type, body) = web::block(move || self.render(&url))
+            .await
+            .expect("the blocking thread pool is running")?;
+        Ok(HttpResponse::Ok().content_type(content_type).body(body))
+    }
+
+    /// Renders a QR code for `url`, returning its content type and bytes.
+    fn render(&self, url: &str) -> Result<(&'static str, Vec<u8>), AppError> {
+        let code = QrCode::with_error_correction_level(url, self.ecc)
+            .map_err(|err| AppError::InvalidInput(format!("Can't encode the URL: {}", err)))?;
+        let modules = code.width() as u32;
+        let total = modules + 2 * self.margin;
+        if total > self.size {
+            return Err(AppError::InvalidInput(format!(
+                "Size must be at least {} pixels for this code",
+                total
+            )));
+        }
+        let colors = code.to_colors();
+        let dark = |x: u32, y: u32| colors[(y * modules + x) as usize] == Color::Dark;
+
+        match self.format {
+            QrFormat::Png => Ok(("image/png", self.render_png(modules, &dark))),
+            QrFormat::Svg => Ok((
+                "image/svg+xml",
+                self.render_svg(modules, &dark).into_bytes(),
+            )),
+        }
+    }
+
+    fn render_png(&self, modules: u32, dark: &dyn Fn(u32, u32) -> bool) -> Vec<u8> {
+        let total = modules + 2 * self.margin;
+        // Whole pixels per module keep the edges sharp, the rest of the
+        // requested size goes to the margin
+        let scale = self.size / total;
+        let offset = (self.size - modules * scale) / 2;
+
+        let mut image = RgbaImage::from_pixel(self.size, self.size, self.light);
+        for y in 0..modules {
+            for x in 0..modules {
+                if dark(x, y) {
+                    for py in 0..scale {
+                        for px in 0..scale {
+                            image.put_pixel(
+                                offset + x * scale + px,
+                                offset + y * scale + py,
+                                self.dark,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(ref logo) = self.logo {
+            let (x, y, width, height) = logo_box(&logo.image, (modules * scale) as f64);
+            let (x, y) = (x as u32 + offset, y as u32 + offset);
+            let (width, height) = (width as u32, height as u32);
+            let pad = scale;
+            for py in y.saturating_sub(pad)..(y + height + pad).min(self.size) {
+                for px in x.saturating_sub(pad)..(x + width + pad).min(self.size) {
+                    image.put_pixel(px, py, self.light);
+                }
+            }
+            let resized = imageops::resize(&logo.image, width, height, FilterType::Lanczos3);
+            imageops::overlay(&mut image, &resized, x.into(), y.into());
+        }
+
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .expect("encoding a PNG into memory can't fail");
+        png
+    }
+
+    fn render_svg(&self, modules: u32, dark: &dyn Fn(u32, u32) -> bool) -> String {
+        let total = modules + 2 * self.margin;
+        let mut path = String::new();
+        for y in 0..modules {
+            for x in 0..modules {
+                if dark(x, y) {
+                    let _ = write!(path, "M{} {}h1v1h-1z", x + self.margin, y + self.margin);
+                }
+            }
+        }
+
+        let mut svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{size}" viewBox="0 0 {total} {total}" shape-rendering="crispEdges"><rect width="{total}" height="{total}" {light}/><path d="{path}" {dark}/>"#,
+            size = self.size,
+            total = total,
+            light = svg_fill(self.light),
+            dark = svg_fill(self.dark),
+            path = path,
+        );
+        if let Some(ref logo) = self.logo {
+            let (x, y, width, height) = logo_box(&logo.image, modules as f64);
+            let margin = self.margin as f64;
+            let _ = write!(
+                svg,
+                r#"<rect x="{}" y="{}" width="{}" height="{}" {}/><image x="{}" y="{}" width="{}" height="{}" href="{}"/>"#,
+                x + margin - 1.0,
+                y + margin - 1.0,
+                width + 2.0,
+                height + 2.0,
+                svg_fill(self.light),
+                x + margin,
+                y + margin,
+                width,
+                height,
+                logo.data_url,
+            );
+        }
+        svg.push_str("</svg>");
+        svg
+    }
+}
+
+/// Where the logo goes on a code `width` units wide, keeping its aspect
+/// ratio: `(x, y, width, height)`.
+fn logo_box(logo: &RgbaImage, width: f64) -> (f64, f64, f64, f64) {
+    let max = (width * LOGO_SCALE).floor().max(1.0);
+    let (logo_width, logo_height) = (logo.width() as f64, logo.height() as f64);
+    let scale = max / logo_width.max(logo_height);
+    let (w, h) = (
+        (logo_width * scale).round().max(1.0),
+        (logo_height * scale).round().max(1.0),
+    );
+    (
+        ((width - w) / 2.0).floor(),
+        ((width - h) / 2.0).floor(),
+        w,
+        h,
+    )
+}
+
+fn svg_fill(color: Rgba<u8>) -> String {
+    svg_paint("fill", color.0)
+}
+
+/// The colour in `value`, or `default` if it's missing.
+fn parse_color(name: &str, value: Option<&str>, default: Rgba<u8>) -> Result<Rgba<u8>, AppError> {
+    match value {
+        Some(value) => parse_hex_color(name, value).map(Rgba),
+        None => Ok(default),
+    }
+}
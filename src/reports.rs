@@ -0,0 +1,225 @@
+//! Protection for the public abuse report endpoint. Anyone can report a
+//! short link without an account, so each address may only send
+//! `REPORT_RATE_LIMIT` reports an hour, and every report needs a solved
+//! proof-of-work challenge: a nonce such that the SHA-256 hash of the
+//! challenge followed by the nonce starts with `REPORT_POW_DIFFICULTY` zero
+//! bits. Challenges are signed rather than stored, so handing them out
+//! costs nothing. Each can only be used once: a used challenge is remembered
+//! until it expires.
+//!
+//! Clients are told apart by the address they connect from. Behind a
+//! reverse proxy, list its addresses in `TRUSTED_PROXIES` so that the
+//! client's address is taken from `X-Forwarded-For` instead.
+
+use crate::error::AppError;
+use actix_web::HttpRequest;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_DIFFICULTY: u32 = 18;
+const DEFAULT_RATE_LIMIT: usize = 5;
+const RATE_WINDOW: Duration = Duration::from_secs(60 * 60);
+pub const CHALLENGE_TTL: Duration = Duration::from_secs(5 * 60);
+/// Bytes of the signature kept in a challenge
+const SIGNATURE_LEN: usize = 16;
+
+#[derive(Default)]
+struct GuardState {
+    /// Challenges that were used, with when they expire as Unix time
+    spent: HashMap<String, u64>,
+    /// Recent reports per client address
+    reports: HashMap<String, VecDeque<Instant>>,
+}
+
+#[derive(Clone)]
+pub struct ReportGuard {
+    difficulty: u32,
+    rate_limit: usize,
+    /// Proxies whose `X-Forwarded-For` headers are believed
+    trusted_proxies: Arc<Vec<IpAddr>>,
+    /// Signs challenges. A new one is made at startup, which only voids the
+    /// challenges handed out before.
+    key: Arc<[u8; 32]>,
+    state: Arc<Mutex<GuardState>>,
+}
+
+impl ReportGuard {
+    /// Reads `REPORT_POW_DIFFICULTY`, `REPORT_RATE_LIMIT` and
+    /// `TRUSTED_PROXIES` (comma-separated IP addresses).
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut trusted_proxies = Vec::new();
+        for proxy in std::env::var("TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+        {
+            let proxy = proxy.trim();
+            if proxy.is_empty() {
+                continue;
+            }
+            let ip = proxy.parse().map_err(|_| {
+                anyhow::anyhow!(
+                    "Invalid TRUSTED_PROXIES entry {:?}, expected an IP address",
+                    proxy
+                )
+            })?;
+            trusted_proxies.push(ip);
+        }
+
+        Ok(Self {
+            difficulty: number_from_env("REPORT_POW_DIFFICULTY", DEFAULT_DIFFICULTY).min(32),
+            rate_limit: number_from_env("REPORT_RATE_LIMIT", DEFAULT_RATE_LIMIT),
+            trusted_proxies: Arc::new(trusted_proxies),
+            key: Arc::new(rand::thread_rng().gen()),
+            state: Arc::default(),
+        })
+    }
+
+    /// The address a request comes from. `X-Forwarded-For` only counts when
+    /// the connection comes from a trusted proxy, and then the client is the
+    /// last address in it that isn't one of the proxies, since everything
+    /// before that was sent by the client itself.
+    pub fn client(&self, req: &HttpRequest) -> String {
+        let Some(peer) = req.peer_addr().map(|addr| addr.ip()) else {
+            return "unknown".to_string();
+        };
+        if !self.trusted_proxies.contains(&peer) {
+            return peer.to_string();
+        }
+
+        let forwarded: Vec<IpAddr> = req
+            .headers()
+            .get_all("X-Forwarded-For")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|ip| ip.trim().parse().ok())
+            .collect();
+        forwarded
+            .into_iter()
+            .rev()
+            .find(|ip| !self.trusted_proxies.contains(ip))
+            .unwrap_or(peer)
+            .to_string()
+    }
+
+    pub fn difficulty(&self) -> u32 {
+        self.difficulty
+    }
+
+    /// Hands out a new challenge: random bytes and an expiry time, signed
+    /// with the server's key.
+    pub fn challenge(&self) -> String {
+        let random: [u8; 16] = rand::thread_rng().gen();
+        let body = format!(
+            "{}{:016x}",
+            hex(&random),
+            unix_now() + CHALLENGE_TTL.as_secs()
+        );
+        let signature = self.mac(&body).finalize().into_bytes();
+        format!("{}{}", body, hex(&signature[..SIGNATURE_LEN]))
+    }
+
+    fn mac(&self, body: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key[..]).expect("HMAC takes keys of any size");
+        mac.update(body.as_bytes());
+        mac
+    }
+
+    /// When a challenge this server signed expires, as Unix time.
+    fn expiry(&self, challenge: &str) -> Option<u64> {
+        let (body, signature) =
+            challenge.split_at_checked(challenge.len().checked_sub(2 * SIGNATURE_LEN)?)?;
+        let signature = unhex(signature)?;
+        self.mac(body).verify_truncated_left(&signature).ok()?;
+        u64::from_str_radix(body.get(32..)?, 16).ok()
+    }
+
+    /// Counts a report from `client` against its hourly limit.
+    pub fn check_rate(&self, client: &str) -> Result<(), AppError> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.reports.retain(|_, times| {
+            while times.front().is_some_and(|t| now - *t > RATE_WINDOW) {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+
+        let times = state.reports.entry(client.to_string()).or_default();
+        if times.len() >= self.rate_limit {
+            return Err(AppError::TooManyRequests);
+        }
+        times.push_back(now);
+        Ok(())
+    }
+
+    /// Uses up a challenge, checking the nonce solves it.
+    pub fn redeem(&self, challenge: &str, nonce: &str) -> Result<(), AppError> {
+        let now = unix_now();
+        let Some(expires) = self.expiry(challenge).filter(|expires| *expires > now) else {
+            return Err(AppError::InvalidInput(
+                "Unknown or expired challenge".to_string(),
+            ));
+        };
+
+        let hash = Sha256::digest(format!("{}{}", challenge, nonce).as_bytes());
+        if leading_zero_bits(&hash) < self.difficulty {
+            return Err(AppError::InvalidInput(
+                "The nonce doesn't solve the challenge".to_string(),
+            ));
+        }
+
+        // Only solved challenges are remembered, which takes work to fill
+        let mut state = self.state.lock().unwrap();
+        state.spent.retain(|_, expires| *expires > now);
+        if state.spent.insert(challenge.to_string(), expires).is_some() {
+            return Err(AppError::InvalidInput(
+                "This challenge was already used".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+fn number_from_env<T: std::str::FromStr + std::fmt::Display + Copy>(name: &str, default: T) -> T {
+    match std::env::var(name) {
+        Ok(value) => value.trim().parse().unwrap_or_else(|_| {
+            tracing::warn!("Invalid {} {:?}, using {}", name, value, default);
+            default
+        }),
+        Err(_) => default,
+    }
+}
@@ -0,0 +1,84 @@
+//! Text helpers shared by the handlers, the importers and the metadata
+//! fetcher: length limits on link text, reading attributes and entities
+//! out of scraped HTML, and the hex colours that image endpoints take.
+
+use crate::error::AppError;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+pub const MAX_TITLE_LEN: usize = 200;
+pub const MAX_DESCRIPTION_LEN: usize = 1000;
+
+lazy_static! {
+    static ref ENTITY_REGEX: Regex =
+        Regex::new(r"&(#[0-9]{1,7}|#[xX][0-9a-fA-F]{1,6}|[a-zA-Z]+);").unwrap();
+    static ref ATTRIBUTE_REGEX: Regex =
+        Regex::new(r#"(?is)([a-z_-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+}
+
+/// Parses a hex colour given as `rgb`, `rrggbb` or `rrggbbaa`, with or
+/// without a leading `#`, into red, green, blue and alpha. `name` is the
+/// parameter the colour came from, for the error message.
+pub fn parse_hex_color(name: &str, value: &str) -> Result<[u8; 4], AppError> {
+    let hex = value.trim().trim_start_matches('#');
+    let digits: Option<Vec<u8>> = hex
+        .chars()
+        .map(|c| c.to_digit(16).map(|digit| digit as u8))
+        .collect();
+    let color = match digits.as_deref() {
+        Some(&[r, g, b]) => Some([r * 17, g * 17, b * 17, 255]),
+        Some(digits) if digits.len() == 6 || digits.len() == 8 => {
+            let mut color = [255; 4];
+            for (channel, pair) in color.iter_mut().zip(digits.chunks(2)) {
+                *channel = pair[0] << 4 | pair[1];
+            }
+            Some(color)
+        }
+        _ => None,
+    };
+    color.ok_or_else(|| {
+        AppError::InvalidInput(format!(
+            "{} must be a hex colour such as 4c1, 2563eb or 2563eb80",
+            name
+        ))
+    })
+}
+
+/// Name/value pairs from the attribute list of an HTML tag. Names are
+/// lowercased and values have their entities decoded.
+pub fn html_attributes(attributes: &str) -> Vec<(String, String)> {
+    ATTRIBUTE_REGEX
+        .captures_iter(attributes)
+        .map(|attr| {
+            let value = attr
+                .get(2)
+                .or_else(|| attr.get(3))
+                .map_or("", |m| m.as_str());
+            (attr[1].to_lowercase(), decode_entities(value))
+        })
+        .collect()
+}
+
+pub fn decode_entities(value: &str) -> String {
+    ENTITY_REGEX
+        .replace_all(value, |captures: &regex::Captures| {
+            let entity = &captures[1];
+            let decoded = match entity {
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "amp" => Some('&'),
+                "nbsp" => Some(' '),
+                _ => {
+                    let code = match entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                        None => entity.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                    };
+                    code.and_then(char::from_u32)
+                }
+            };
+            decoded.map_or_else(|| captures[0].to_string(), String::from)
+        })
+        .into_owned()
+}
@@ -0,0 +1,170 @@
+//! Purging of deleted links. `delete_link` only moves a link to the trash;
+//! a background job removes it, along with its clicks and tags, once it has
+//! been there for `TRASH_RETENTION_DAYS` days. The purged link's short code
+//! and aliases are kept as tombstones so nobody else can claim them for
+//! `CODE_TOMBSTONE_DAYS` days, or ever if that is unset.
+
+use crate::models::DatabasePool;
+use std::time::Duration;
+
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Copy, Debug)]
+pub struct TrashConfig {
+    /// Days a deleted link is kept before it is purged. `None` means the
+    /// trash is never emptied.
+    pub retention_days: Option<i64>,
+    /// Days a retired short code stays blocked. `None` blocks it until an
+    /// admin releases it.
+    pub tombstone_days: Option<i64>,
+}
+
+impl TrashConfig {
+    pub fn from_env() -> Self {
+        Self {
+            retention_days: days_from_env("TRASH_RETENTION_DAYS", Some(DEFAULT_RETENTION_DAYS)),
+            tombstone_days: days_from_env("CODE_TOMBSTONE_DAYS", None),
+        }
+    }
+}
+
+/// Reads a day count where 0 means "forever".
+fn days_from_env(name: &str, default: Option<i64>) -> Option<i64> {
+    match std::env::var(name) {
+        Ok(value) => match value.trim().parse::<i64>() {
+            Ok(0) => None,
+            Ok(days) if days > 0 => Some(days),
+            _ => {
+                tracing::warn!("Invalid {} {:?}, using {:?}", name, value, default);
+                default
+            }
+        },
+        Err(_) => default,
+    }
+}
+
+/// Starts the hourly purge job.
+pub fn spawn_purge_job(db: DatabasePool, config: TrashConfig) {
+    let Some(days) = config.retention_days else {
+        tracing::info!("Trash retention is unlimited, deleted links are never purged");
+        return;
+    };
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match purge_deleted_links(&db, days, config.tombstone_days).await {
+                Ok(0) => {}
+                Ok(purged) => tracing::info!("Purged {} links from the trash", purged),
+                Err(err) => tracing::error!("Failed to purge the trash: {}", err),
+            }
+        }
+    });
+}
+
+/// Permanently removes links that were deleted more than `days` days ago,
+/// leaving a tombstone for each of their codes and aliases.
+pub async fn purge_deleted_links(
+    db: &DatabasePool,
+    days: i64,
+    tombstone_days: Option<i64>,
+) -> anyhow::Result<u64> {
+    let purged = match db {
+        DatabasePool::Postgres(pool) => {
+            let mut tx = pool.begin().await?;
+            let expired =
+                "SELECT id FROM links WHERE deleted_at < NOW() - make_interval(days => $1)";
+
+            sqlx::query(
+                r#"
+                INSERT INTO code_tombstones (short_code, user_id, expires_at)
+                SELECT link_codes.short_code, links.user_id, NOW() + make_interval(days => $2)
+                FROM link_codes
+                JOIN links ON links.id = link_codes.link_id
+                WHERE links.deleted_at < NOW() - make_interval(days => $1)
+                ON CONFLICT (short_code) DO UPDATE
+                SET user_id = EXCLUDED.user_id,
+                    released_at = NOW(),
+                    expires_at = EXCLUDED.expires_at
+                "#,
+            )
+            .bind(days as i32)
+            .bind(tombstone_days.map(|d| d as i32))
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(&format!(
+                "DELETE FROM clicks WHERE link_id IN ({})",
+                expired
+            ))
+            .bind(days as i32)
+            .execute(&mut *tx)
+            .await?;
+            for table in ["link_tags", "link_codes", "link_revisions", "abuse_reports"] {
+                sqlx::query(&format!(
+                    "DELETE FROM {} WHERE link_id IN ({})",
+                    table, expired
+                ))
+                .bind(days as i32)
+                .execute(&mut *tx)
+                .await?;
+            }
+            let result = sqlx::query(&format!("DELETE FROM links WHERE id IN ({})", expired))
+                .bind(days as i32)
+                .execute(&mut *tx)
+                .await?;
+
+            tx.commit().await?;
+            result.rows_affected()
+        }
+        DatabasePool::Sqlite(pool) => {
+            let mut tx = pool.begin().await?;
+            let expired = "SELECT id FROM links WHERE datetime(deleted_at) < datetime('now', '-' || ?1 || ' days')";
+
+            sqlx::query(
+                r#"
+                INSERT INTO code_tombstones (short_code, user_id, expires_at)
+                SELECT link_codes.short_code, links.user_id,
+                    CASE WHEN ?2 IS NULL THEN NULL
+                    ELSE datetime('now', '+' || ?2 || ' days') END
+                FROM link_codes
+                JOIN links ON links.id = link_codes.link_id
+                WHERE datetime(links.deleted_at) < datetime('now', '-' || ?1 || ' days')
+                ON CONFLICT (short_code) DO UPDATE
+                SET user_id = excluded.user_id,
+                    released_at = CURRENT_TIMESTAMP,
+                    expires_at = excluded.expires_at
+                "#,
+            )
+            .bind(days)
+            .bind(tombstone_days)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(&format!(
+                "DELETE FROM clicks WHERE link_id IN ({})",
+                expired
+            ))
+            .bind(days)
+            .execute(&mut *tx)
+            .await?;
+            for table in ["link_tags", "link_codes", "link_revisions", "abuse_reports"] {
+                sqlx::query(&format!(
+                    "DELETE FROM {} WHERE link_id IN ({})",
+                    table, expired
+                ))
+                .bind(days)
+                .execute(&mut *tx)
+                .await?;
+            }
+            let result = sqlx::query(&format!("DELETE FROM links WHERE id IN ({})", expired))
+                .bind(days)
+                .execute(&mut *tx)
+                .await?;
+
+            tx.commit().await?;
+            result.rows_affected()
+        }
+    };
+    Ok(purged)
+}